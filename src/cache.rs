@@ -0,0 +1,169 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::provider::Container;
+
+/// The last collection plan that was successfully fetched for an address.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedPlan {
+    pub fetched_at: DateTime<Utc>,
+    pub containers: Vec<Container>,
+}
+
+impl CachedPlan {
+    pub fn age(&self) -> Duration {
+        Utc::now() - self.fetched_at
+    }
+}
+
+/// Keeps the last good collection plan of an address on disk, so it can be served when the remote is down.
+pub struct PlanCache {
+    path: PathBuf,
+}
+
+impl PlanCache {
+    pub fn new(state_dir: &Path, id: &str) -> Self {
+        Self {
+            path: state_dir.join(format!("{}.plan.json", id)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn store(&self, containers: &[Container]) -> io::Result<()> {
+        write_json(
+            &self.path,
+            &CachedPlanRef {
+                fetched_at: Utc::now(),
+                containers,
+            },
+        )
+    }
+
+    pub fn load(&self) -> Option<CachedPlan> {
+        read_json(&self.path)
+    }
+}
+
+#[derive(Serialize)]
+struct CachedPlanRef<'a> {
+    fetched_at: DateTime<Utc>,
+    containers: &'a [Container],
+}
+
+/// Remembers which address id a looked up address resolved to, so the lookup service
+/// is only needed the first time an address is seen.
+pub struct AddressCache {
+    path: PathBuf,
+}
+
+impl AddressCache {
+    pub fn new(state_dir: &Path) -> Self {
+        Self {
+            path: state_dir.join("addresses.json"),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries().remove(key)
+    }
+
+    pub fn insert(&self, key: &str, id: &str) -> io::Result<()> {
+        let mut entries = self.entries();
+        entries.insert(key.to_owned(), id.to_owned());
+
+        write_json(&self.path, &entries)
+    }
+
+    fn entries(&self) -> BTreeMap<String, String> {
+        read_json(&self.path).unwrap_or_default()
+    }
+}
+
+/// Reads a JSON file, a missing or unreadable file is treated as absent.
+pub(crate) fn read_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let content = fs::read(path).ok()?;
+
+    serde_json::from_slice(&content).ok()
+}
+
+/// Writes a JSON file through a temporary file, so a crash never leaves a truncated file behind.
+pub(crate) fn write_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, serde_json::to_vec_pretty(value)?)?;
+    fs::rename(temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(name: &str) -> Container {
+        Container {
+            name: name.to_string(),
+            date: "2024-04-18".parse().unwrap(),
+            stand_id: "68460".to_string(),
+            stand_name: "Kongevejen 100, 8000 Aarhus C".to_string(),
+        }
+    }
+
+    #[test]
+    fn can_round_trip_plan() {
+        let state_dir = tempfile::tempdir().unwrap();
+        let cache = PlanCache::new(state_dir.path(), "affaldvarme");
+
+        cache
+            .store(&[container("Restaffald"), container("Glas")])
+            .unwrap();
+        let actual = cache.load().unwrap();
+
+        assert_eq!(
+            actual.containers,
+            vec![container("Restaffald"), container("Glas")]
+        );
+        assert!(actual.age() < Duration::minutes(1));
+    }
+
+    #[test]
+    fn can_remember_address_ids() {
+        let state_dir = tempfile::tempdir().unwrap();
+        let cache = AddressCache::new(state_dir.path());
+
+        cache
+            .insert("kongevejen|100", "07514448_100_______")
+            .unwrap();
+        cache
+            .insert("kongevejen|102", "07514448_102_______")
+            .unwrap();
+
+        assert_eq!(
+            cache.get("kongevejen|100").as_deref(),
+            Some("07514448_100_______")
+        );
+        assert_eq!(
+            cache.get("kongevejen|102").as_deref(),
+            Some("07514448_102_______")
+        );
+        assert!(cache.get("kongevejen|104").is_none());
+    }
+
+    #[test]
+    fn missing_plan_is_absent() {
+        let state_dir = tempfile::tempdir().unwrap();
+        let cache = PlanCache::new(state_dir.path(), "affaldvarme");
+
+        assert!(cache.load().is_none());
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::{fractions::Fraction, provider::Container};
+
+/// Pickups that moved by at most this many days are reported as moved, not as removed and added.
+const MAX_MOVE_DAYS: i64 = 7;
+
+/// A difference between two collection plans of an address.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+pub enum ScheduleChange {
+    /// The pickup planned on `from` takes place on `to` instead, e.g. because of a public holiday.
+    Moved {
+        name: String,
+        stand_id: String,
+        stand_name: String,
+        from: NaiveDate,
+        to: NaiveDate,
+    },
+    Added {
+        name: String,
+        stand_id: String,
+        stand_name: String,
+        date: NaiveDate,
+    },
+    Removed {
+        name: String,
+        stand_id: String,
+        stand_name: String,
+        date: NaiveDate,
+    },
+}
+
+/// The pickups that changed from `previous` to `current`.
+///
+/// Only dates from `today` up to the end of the shorter plan are compared, pickups falling into
+/// the past or appearing as the plan reaches further ahead are not changes.
+pub fn detect(
+    previous: &[Container],
+    current: &[Container],
+    today: NaiveDate,
+) -> Vec<ScheduleChange> {
+    let until = match (last_date(previous), last_date(current)) {
+        (Some(previous), Some(current)) => previous.min(current),
+        _ => return Vec::new(),
+    };
+    let compared = |x: &&Container| (today..=until).contains(&x.date);
+
+    // keyed like the sensors, see FractionSchedule::group, and named like them by the current plan
+    let mut fractions: BTreeMap<(&str, String), FractionDates> = BTreeMap::new();
+    for container in current.iter().filter(compared) {
+        let fraction = fractions
+            .entry((
+                &container.stand_id,
+                Fraction::from_name(&container.name).slug(),
+            ))
+            .or_default();
+        if fraction.name.is_empty() {
+            fraction.name = &container.name;
+        }
+        // a stand can be renamed without anything else changing
+        fraction.stand_name = &container.stand_name;
+        fraction.after.push(container.date);
+    }
+    for container in previous.iter().filter(compared) {
+        let fraction = fractions
+            .entry((
+                &container.stand_id,
+                Fraction::from_name(&container.name).slug(),
+            ))
+            .or_default();
+        if fraction.name.is_empty() {
+            fraction.name = &container.name;
+            fraction.stand_name = &container.stand_name;
+        }
+        fraction.before.push(container.date);
+    }
+
+    let mut changes = Vec::new();
+    for ((stand_id, _), fraction) in fractions {
+        let FractionDates {
+            name,
+            stand_name,
+            before,
+            after,
+        } = fraction;
+        let removed: Vec<NaiveDate> = before
+            .iter()
+            .filter(|x| !after.contains(x))
+            .copied()
+            .collect();
+        let mut added: Vec<NaiveDate> = after
+            .iter()
+            .filter(|x| !before.contains(x))
+            .copied()
+            .collect();
+
+        let mut fraction_changes = Vec::new();
+        for date in removed {
+            let closest = added
+                .iter()
+                .enumerate()
+                .map(|(i, x)| (i, (*x - date).num_days().abs()))
+                .filter(|(_, distance)| *distance <= MAX_MOVE_DAYS)
+                .min_by_key(|(_, distance)| *distance);
+
+            fraction_changes.push(match closest {
+                Some((i, _)) => (date, Change::Moved(added.remove(i))),
+                None => (date, Change::Removed),
+            });
+        }
+        fraction_changes.extend(added.into_iter().map(|x| (x, Change::Added)));
+        fraction_changes.sort_by_key(|(date, _)| *date);
+        fraction_changes.dedup();
+
+        changes.extend(fraction_changes.into_iter().map(|(date, change)| {
+            let (name, stand_id, stand_name) = (
+                name.to_string(),
+                stand_id.to_string(),
+                stand_name.to_string(),
+            );
+            match change {
+                Change::Moved(to) => ScheduleChange::Moved {
+                    name,
+                    stand_id,
+                    stand_name,
+                    from: date,
+                    to,
+                },
+                Change::Added => ScheduleChange::Added {
+                    name,
+                    stand_id,
+                    stand_name,
+                    date,
+                },
+                Change::Removed => ScheduleChange::Removed {
+                    name,
+                    stand_id,
+                    stand_name,
+                    date,
+                },
+            }
+        }));
+    }
+
+    changes
+}
+
+/// The compared dates of one fraction at one stand.
+#[derive(Default)]
+struct FractionDates<'a> {
+    name: &'a str,
+    stand_name: &'a str,
+    before: Vec<NaiveDate>,
+    after: Vec<NaiveDate>,
+}
+
+#[derive(PartialEq)]
+enum Change {
+    Moved(NaiveDate),
+    Added,
+    Removed,
+}
+
+fn last_date(containers: &[Container]) -> Option<NaiveDate> {
+    containers.iter().map(|x| x.date).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(name: &str, date: &str) -> Container {
+        Container::new(
+            name.to_string(),
+            date.parse().unwrap(),
+            "68460".to_string(),
+            "Kongevejen 100, 8000 Aarhus C".to_string(),
+        )
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn unchanged_plan_has_no_changes() {
+        let plan = vec![
+            container("Restaffald", "2024-04-18"),
+            container("Restaffald", "2024-05-02"),
+        ];
+
+        assert_eq!(detect(&plan, &plan, date("2024-04-17")), Vec::new());
+    }
+
+    #[test]
+    fn detects_moved_added_and_removed_pickups() {
+        let previous = vec![
+            container("Restaffald", "2024-04-18"),
+            container("Restaffald", "2024-05-02"),
+            container("Glas", "2024-04-26"),
+            container("Papir", "2024-05-09"),
+        ];
+        let current = vec![
+            container("Restaffald", "2024-04-18"),
+            container("Restaffald", "2024-05-04"),
+            container("Glas", "2024-04-26"),
+            container("Glas", "2024-05-03"),
+            container("Papir", "2024-05-23"),
+        ];
+
+        let actual = detect(&previous, &current, date("2024-04-17"));
+
+        let stand = || {
+            (
+                "68460".to_string(),
+                "Kongevejen 100, 8000 Aarhus C".to_string(),
+            )
+        };
+        assert_eq!(
+            actual,
+            vec![
+                ScheduleChange::Added {
+                    name: "Glas".to_string(),
+                    stand_id: stand().0,
+                    stand_name: stand().1,
+                    date: date("2024-05-03"),
+                },
+                ScheduleChange::Removed {
+                    name: "Papir".to_string(),
+                    stand_id: stand().0,
+                    stand_name: stand().1,
+                    date: date("2024-05-09"),
+                },
+                ScheduleChange::Moved {
+                    name: "Restaffald".to_string(),
+                    stand_id: stand().0,
+                    stand_name: stand().1,
+                    from: date("2024-05-02"),
+                    to: date("2024-05-04"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_past_pickups_and_extended_horizon() {
+        let previous = vec![
+            container("Restaffald", "2024-04-04"),
+            container("Restaffald", "2024-04-18"),
+        ];
+        let current = vec![
+            container("Restaffald", "2024-04-18"),
+            container("Restaffald", "2024-05-02"),
+        ];
+
+        assert_eq!(detect(&previous, &current, date("2024-04-17")), Vec::new());
+    }
+
+    #[test]
+    fn serializes_as_event() {
+        let change = ScheduleChange::Moved {
+            name: "Restaffald".to_string(),
+            stand_id: "68460".to_string(),
+            stand_name: "Kongevejen 100, 8000 Aarhus C".to_string(),
+            from: date("2024-05-02"),
+            to: date("2024-05-04"),
+        };
+
+        assert_eq!(
+            serde_json::to_value(change).unwrap(),
+            serde_json::json!({
+                "event_type": "moved",
+                "name": "Restaffald",
+                "stand_id": "68460",
+                "stand_name": "Kongevejen 100, 8000 Aarhus C",
+                "from": "2024-05-02",
+                "to": "2024-05-04",
+            })
+        );
+    }
+
+    #[test]
+    fn compares_names_of_the_same_sensor_together() {
+        let previous = vec![
+            container("Plast/metal", "2024-04-25"),
+            container("Plast/metal", "2024-05-02"),
+        ];
+        let current = vec![
+            container("Plast metal", "2024-04-25"),
+            container("Plast metal", "2024-05-02"),
+        ];
+
+        assert_eq!(detect(&previous, &current, date("2024-04-20")), vec![]);
+    }
+}
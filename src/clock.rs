@@ -0,0 +1,121 @@
+use chrono::{DateTime, Days, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::{Europe::Copenhagen, Tz};
+
+/// Collection schedules are planned in Danish local time, a new day starts at Danish midnight.
+pub const DEFAULT_TIMEZONE: Tz = Copenhagen;
+
+/// The current date in `timezone`, pickups before it are in the past.
+pub fn today(timezone: Tz) -> NaiveDate {
+    local_date(Utc::now(), timezone)
+}
+
+/// The date `moment` falls on in `timezone`.
+pub fn local_date(moment: DateTime<Utc>, timezone: Tz) -> NaiveDate {
+    moment.with_timezone(&timezone).date_naive()
+}
+
+/// The first moment of the next day in `timezone`.
+pub fn next_midnight(now: DateTime<Utc>, timezone: Tz) -> DateTime<Utc> {
+    let tomorrow = local_date(now, timezone) + Days::new(1);
+    let midnight = tomorrow.and_time(NaiveTime::MIN);
+
+    // Midnight can fall into a DST gap in some timezones, the day then starts an hour later.
+    timezone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(midnight + Duration::hours(1)))
+                .earliest()
+        })
+        .expect("A day always starts within its first hour")
+        .with_timezone(&Utc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn next_midnight_is_local() {
+        assert_eq!(
+            next_midnight(utc("2024-01-15T12:00:00Z"), Copenhagen),
+            utc("2024-01-15T23:00:00Z")
+        );
+        assert_eq!(
+            next_midnight(utc("2024-07-15T12:00:00Z"), Copenhagen),
+            utc("2024-07-15T22:00:00Z")
+        );
+    }
+
+    #[test]
+    fn next_midnight_follows_the_local_date() {
+        // 23:30 UTC is already the next day in Denmark
+        assert_eq!(
+            next_midnight(utc("2024-01-15T23:30:00Z"), Copenhagen),
+            utc("2024-01-16T23:00:00Z")
+        );
+    }
+
+    #[test]
+    fn next_midnight_across_dst_weekends() {
+        // summer time starts 2024-03-31 at 02:00, the day before still starts at +01:00
+        assert_eq!(
+            next_midnight(utc("2024-03-30T12:00:00Z"), Copenhagen),
+            utc("2024-03-30T23:00:00Z")
+        );
+        assert_eq!(
+            next_midnight(utc("2024-03-31T12:00:00Z"), Copenhagen),
+            utc("2024-03-31T22:00:00Z")
+        );
+        // summer time ends 2024-10-27 at 03:00
+        assert_eq!(
+            next_midnight(utc("2024-10-26T12:00:00Z"), Copenhagen),
+            utc("2024-10-26T22:00:00Z")
+        );
+        assert_eq!(
+            next_midnight(utc("2024-10-27T12:00:00Z"), Copenhagen),
+            utc("2024-10-27T23:00:00Z")
+        );
+    }
+
+    #[test]
+    fn local_date_across_dst_weekends() {
+        assert_eq!(
+            local_date(utc("2024-03-30T22:59:59Z"), Copenhagen),
+            date("2024-03-30")
+        );
+        assert_eq!(
+            local_date(utc("2024-03-30T23:00:00Z"), Copenhagen),
+            date("2024-03-31")
+        );
+        assert_eq!(
+            local_date(utc("2024-03-31T21:59:59Z"), Copenhagen),
+            date("2024-03-31")
+        );
+        assert_eq!(
+            local_date(utc("2024-03-31T22:00:00Z"), Copenhagen),
+            date("2024-04-01")
+        );
+        assert_eq!(
+            local_date(utc("2024-10-26T22:00:00Z"), Copenhagen),
+            date("2024-10-27")
+        );
+        assert_eq!(
+            local_date(utc("2024-10-27T22:59:59Z"), Copenhagen),
+            date("2024-10-27")
+        );
+        assert_eq!(
+            local_date(utc("2024-10-27T23:00:00Z"), Copenhagen),
+            date("2024-10-28")
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+    path::PathBuf,
+};
+
+use reqwest::StatusCode;
+use rumqttc::v5::mqttbytes::v5::PubAckReason;
+use url::Url;
+
+use crate::homeassistant::reason_code;
+use crate::mitaffald::dawa::AddressCandidate;
+
+/// Everything that can go wrong while synchronizing, callers can match on the kind of failure.
+#[derive(Debug)]
+pub enum Error {
+    /// The remote could not be reached (DNS, refused connection, timeout...).
+    Transport(reqwest::Error),
+    /// The remote answered with a non successful status code.
+    HttpStatus { url: Url, status: StatusCode },
+    /// The remote did not answer successfully within the configured deadline.
+    DeadlineExceeded,
+    /// The remote answered, but the content could not be understood.
+    Decode(reqwest::Error),
+    /// A local file could not be read.
+    ReadFile { path: PathBuf, err: io::Error },
+    /// A local file could not be written.
+    WriteFile { path: PathBuf, err: io::Error },
+    /// The content is not a usable iCalendar feed.
+    InvalidCalendar(String),
+    /// The remote answered with an empty collection plan.
+    NoData,
+    /// The address lookup did not return any match.
+    AddressNotFound { query: String },
+    /// The address lookup returned more than one match, the candidates are ranked.
+    AddressAmbiguous {
+        query: String,
+        candidates: Vec<AddressCandidate>,
+    },
+    /// The TLS settings of the MQTT connection are not usable.
+    InvalidTls(String),
+    /// A message could not be handed over to the MQTT client.
+    MqttClient(rumqttc::ClientError),
+    /// The connection to the MQTT broker failed.
+    MqttConnection(rumqttc::ConnectionError),
+    /// A message could not be handed over to the MQTT 5 client.
+    MqttV5Client(Box<rumqttc::v5::ClientError>),
+    /// The connection to the MQTT 5 broker failed, e.g. it disconnected with a reason code.
+    MqttV5Connection(rumqttc::v5::ConnectionError),
+    /// The MQTT 5 broker refused a message, `description` is the reason string it sent along.
+    PublishRejected {
+        topic: String,
+        reason: PubAckReason,
+        description: Option<String>,
+    },
+    /// Some of the work failed, the rest went through.
+    Incomplete(Vec<Error>),
+}
+
+impl Error {
+    /// Combines `errors` into one: none, the only one, or [`Error::Incomplete`] with the errors of
+    /// nested [`Error::Incomplete`]s taken out, so every error in it is of a specific kind.
+    pub fn collect(errors: Vec<Error>) -> Option<Error> {
+        let mut errors: Vec<Error> = errors
+            .into_iter()
+            .flat_map(|err| match err {
+                Error::Incomplete(errors) => errors,
+                err => vec![err],
+            })
+            .collect();
+
+        match errors.len() {
+            0 => None,
+            1 => errors.pop(),
+            _ => Some(Error::Incomplete(errors)),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(err) => write!(f, "Error connecting: {}", err),
+            Error::HttpStatus { url, status } => {
+                write!(f, "Unexpected status code: {} from {}", status, url)
+            }
+            Error::DeadlineExceeded => write!(f, "Deadline exceeded while waiting for the remote"),
+            Error::Decode(err) => write!(f, "Error reading response content: {}", err),
+            Error::ReadFile { path, err } => {
+                write!(f, "Error reading {}: {}", path.display(), err)
+            }
+            Error::WriteFile { path, err } => {
+                write!(f, "Error writing {}: {}", path.display(), err)
+            }
+            Error::InvalidCalendar(reason) => write!(f, "Invalid calendar: {}", reason),
+            Error::NoData => write!(f, "No data found"),
+            Error::AddressNotFound { query } => write!(f, "Address not found: {}", query),
+            Error::AddressAmbiguous { query, candidates } => {
+                write!(
+                    f,
+                    "Multiple addresses matched '{}', specify floor and door:",
+                    query
+                )?;
+                for candidate in candidates {
+                    write!(f, " [{} ({})]", candidate.text, candidate.kvhx)?;
+                }
+                Ok(())
+            }
+            Error::InvalidTls(reason) => write!(f, "Invalid MQTT TLS settings: {}", reason),
+            Error::MqttClient(err) => write!(f, "MQTT client error: {}", err),
+            Error::MqttConnection(err) => write!(f, "MQTT connection error: {}", err),
+            Error::MqttV5Client(err) => write!(f, "MQTT client error: {}", err),
+            Error::MqttV5Connection(err) => write!(f, "MQTT connection error: {}", err),
+            Error::PublishRejected {
+                topic,
+                reason,
+                description,
+            } => {
+                write!(
+                    f,
+                    "Broker rejected the message to {}: {:?} (0x{:02x})",
+                    topic,
+                    reason,
+                    reason_code(*reason)
+                )?;
+                match description {
+                    Some(description) => write!(f, ", {}", description),
+                    None => Ok(()),
+                }
+            }
+            Error::Incomplete(errors) => {
+                write!(f, "{} operation(s) failed: [", errors.len())?;
+                for (i, err) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", err)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(err) | Error::Decode(err) => Some(err),
+            Error::ReadFile { err, .. } | Error::WriteFile { err, .. } => Some(err),
+            Error::MqttClient(err) => Some(err),
+            Error::MqttConnection(err) => Some(err),
+            Error::MqttV5Client(err) => Some(err),
+            Error::MqttV5Connection(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rumqttc::ClientError> for Error {
+    fn from(err: rumqttc::ClientError) -> Self {
+        Error::MqttClient(err)
+    }
+}
+
+impl From<rumqttc::ConnectionError> for Error {
+    fn from(err: rumqttc::ConnectionError) -> Self {
+        Error::MqttConnection(err)
+    }
+}
+
+impl From<rumqttc::v5::ClientError> for Error {
+    fn from(err: rumqttc::v5::ClientError) -> Self {
+        Error::MqttV5Client(Box::new(err))
+    }
+}
+
+impl From<rumqttc::v5::ConnectionError> for Error {
+    fn from(err: rumqttc::v5::ConnectionError) -> Self {
+        Error::MqttV5Connection(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_nested_errors_flat() {
+        let nested = Error::collect(vec![Error::NoData, Error::DeadlineExceeded]).unwrap();
+
+        let actual = Error::collect(vec![nested, Error::AddressNotFound { query: "x".into() }]);
+
+        assert!(matches!(
+            actual,
+            Some(Error::Incomplete(errors)) if matches!(
+                errors.as_slice(),
+                [Error::NoData, Error::DeadlineExceeded, Error::AddressNotFound { .. }]
+            )
+        ));
+    }
+
+    #[test]
+    fn collects_a_single_error_as_is() {
+        assert!(Error::collect(Vec::new()).is_none());
+        assert!(matches!(
+            Error::collect(vec![Error::Incomplete(vec![Error::NoData])]),
+            Some(Error::NoData)
+        ));
+    }
+}
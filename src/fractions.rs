@@ -0,0 +1,447 @@
+use std::collections::BTreeMap;
+
+use tracing::info;
+
+use crate::{
+    provider::{Container, FractionSchedule},
+    settings::{FractionConfig, Language},
+};
+
+/// Icon of fractions that are not in the catalogue.
+pub const DEFAULT_ICON: &str = "mdi:recycle";
+
+/// The fractions known by name, anything else is kept as [`Fraction::Other`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fraction {
+    Restaffald,
+    Madaffald,
+    Plast,
+    Glas,
+    Metal,
+    Papir,
+    Pap,
+    PapirOgPap,
+    Tekstiler,
+    MadOgDrikkekartoner,
+    FarligtAffald,
+    Haveaffald,
+    Storskrald,
+    Batterier,
+    SmaatElektronik,
+    Other(String),
+}
+
+struct Entry {
+    slug: &'static str,
+    danish: &'static str,
+    english: &'static str,
+    icon: &'static str,
+}
+
+impl Entry {
+    fn new(
+        slug: &'static str,
+        danish: &'static str,
+        english: &'static str,
+        icon: &'static str,
+    ) -> Self {
+        Self {
+            slug,
+            danish,
+            english,
+            icon,
+        }
+    }
+}
+
+const CATALOGUE: [Fraction; 15] = [
+    Fraction::Restaffald,
+    Fraction::Madaffald,
+    Fraction::Plast,
+    Fraction::Glas,
+    Fraction::Metal,
+    Fraction::Papir,
+    Fraction::Pap,
+    Fraction::PapirOgPap,
+    Fraction::Tekstiler,
+    Fraction::MadOgDrikkekartoner,
+    Fraction::FarligtAffald,
+    Fraction::Haveaffald,
+    Fraction::Storskrald,
+    Fraction::Batterier,
+    Fraction::SmaatElektronik,
+];
+
+impl Fraction {
+    /// The catalogue fraction with this Danish or English name, ignoring case.
+    pub fn from_name(name: &str) -> Self {
+        let name = name.trim();
+
+        CATALOGUE
+            .into_iter()
+            .find(|x| {
+                x.entry().is_ok_and(|entry| {
+                    entry.danish.to_lowercase() == name.to_lowercase()
+                        || entry.english.eq_ignore_ascii_case(name)
+                })
+            })
+            .unwrap_or_else(|| Fraction::Other(name.to_string()))
+    }
+
+    /// Stable identifier, used for the entity ids.
+    pub fn slug(&self) -> String {
+        match self.entry() {
+            Ok(entry) => entry.slug.to_string(),
+            Err(name) => slugify(name),
+        }
+    }
+
+    /// Display name, fractions outside the catalogue keep the name they were reported with.
+    pub fn name(&self, language: Language) -> &str {
+        match self.entry() {
+            Ok(entry) => match language {
+                Language::Da => entry.danish,
+                Language::En => entry.english,
+            },
+            Err(name) => name,
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        self.entry().map(|x| x.icon).unwrap_or(DEFAULT_ICON)
+    }
+
+    /// The catalogue entry, or the reported name of a fraction outside the catalogue.
+    fn entry(&self) -> Result<Entry, &str> {
+        Ok(match self {
+            Fraction::Restaffald => Entry::new(
+                "restaffald",
+                "Restaffald",
+                "Residual waste",
+                "mdi:trash-can",
+            ),
+            Fraction::Madaffald => {
+                Entry::new("madaffald", "Madaffald", "Food waste", "mdi:food-apple")
+            }
+            Fraction::Plast => Entry::new("plast", "Plast", "Plastic", "mdi:bottle-soda-classic"),
+            Fraction::Glas => Entry::new("glas", "Glas", "Glass", "mdi:glass-fragile"),
+            Fraction::Metal => Entry::new("metal", "Metal", "Metal", "mdi:silverware-variant"),
+            Fraction::Papir => Entry::new("papir", "Papir", "Paper", "mdi:newspaper"),
+            Fraction::Pap => Entry::new("pap", "Pap", "Cardboard", "mdi:package-variant"),
+            Fraction::PapirOgPap => Entry::new(
+                "papir_og_pap",
+                "Papir og pap",
+                "Paper and cardboard",
+                "mdi:package-variant",
+            ),
+            Fraction::Tekstiler => {
+                Entry::new("tekstiler", "Tekstiler", "Textiles", "mdi:tshirt-crew")
+            }
+            Fraction::MadOgDrikkekartoner => Entry::new(
+                "mad_og_drikkekartoner",
+                "Mad- og drikkekartoner",
+                "Food and drink cartons",
+                "mdi:food-takeout-box",
+            ),
+            Fraction::FarligtAffald => Entry::new(
+                "farligt_affald",
+                "Farligt affald",
+                "Hazardous waste",
+                "mdi:biohazard",
+            ),
+            Fraction::Haveaffald => {
+                Entry::new("haveaffald", "Haveaffald", "Garden waste", "mdi:leaf")
+            }
+            Fraction::Storskrald => {
+                Entry::new("storskrald", "Storskrald", "Bulky waste", "mdi:sofa")
+            }
+            Fraction::Batterier => Entry::new("batterier", "Batterier", "Batteries", "mdi:battery"),
+            Fraction::SmaatElektronik => Entry::new(
+                "smaat_elektronik",
+                "Småt elektronik",
+                "Small electronics",
+                "mdi:cellphone",
+            ),
+            Fraction::Other(name) => return Err(name),
+        })
+    }
+}
+
+/// Applies the `[fractions]` settings to what the providers report.
+pub struct FractionMapping<'a> {
+    fractions: &'a BTreeMap<String, FractionConfig>,
+}
+
+impl<'a> FractionMapping<'a> {
+    pub fn new(fractions: &'a BTreeMap<String, FractionConfig>) -> Self {
+        Self { fractions }
+    }
+
+    /// Drops hidden fractions and renames the rest, renamed fractions keep their dates.
+    pub fn apply(&self, containers: Vec<Container>) -> Vec<Container> {
+        containers
+            .into_iter()
+            .filter_map(|mut container| match self.fractions.get(&container.name) {
+                Some(config) if config.hide => {
+                    info!("Hiding fraction '{}'", container.name);
+                    None
+                }
+                Some(FractionConfig {
+                    name: Some(name), ..
+                }) => {
+                    container.name = name.clone();
+                    Some(container)
+                }
+                _ => Some(container),
+            })
+            .collect()
+    }
+
+    /// Marks the fractions published under a configured name, so that name is kept as is.
+    pub fn mark_renamed(&self, schedules: &mut [FractionSchedule]) {
+        for schedule in schedules {
+            schedule.renamed = self
+                .fractions
+                .values()
+                .any(|x| x.name.as_ref() == Some(&schedule.name));
+        }
+    }
+
+    /// Sets the configured icons. An icon configured for the name a fraction is published under
+    /// wins, otherwise a merged fraction takes the icon of the first of its original fractions
+    /// that has one, in alphabetical order of their names.
+    pub fn assign_icons(&self, schedules: &mut [FractionSchedule]) {
+        for schedule in schedules {
+            let own = self
+                .fractions
+                .get(&schedule.name)
+                .and_then(|x| x.icon.as_ref());
+            let merged = self
+                .fractions
+                .values()
+                .filter(|x| x.name.as_ref() == Some(&schedule.name))
+                .find_map(|x| x.icon.as_ref());
+
+            if let Some(icon) = own.or(merged) {
+                schedule.icon = Some(icon.clone());
+            }
+        }
+    }
+}
+
+/// Lowercase ASCII letters, digits and single underscores, as Home Assistant expects in object ids.
+/// Danish letters are transliterated (æ→ae, ø→oe, å→aa, é→e), anything else becomes an underscore.
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars().flat_map(char::to_lowercase) {
+        match c {
+            'æ' => slug.push_str("ae"),
+            'ø' => slug.push_str("oe"),
+            'å' => slug.push_str("aa"),
+            'é' => slug.push('e'),
+            c if c.is_ascii_alphanumeric() => slug.push(c),
+            _ if slug.is_empty() || slug.ends_with('_') => {}
+            _ => slug.push('_'),
+        }
+    }
+
+    let slug = slug.trim_end_matches('_');
+    if slug.is_empty() {
+        "unknown".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn recognizes_catalogue_fractions() {
+        assert_eq!(
+            Fraction::from_name("Mad- og drikkekartoner"),
+            Fraction::MadOgDrikkekartoner
+        );
+        assert_eq!(Fraction::from_name(" restaffald "), Fraction::Restaffald);
+        assert_eq!(
+            Fraction::from_name("SMÅT ELEKTRONIK"),
+            Fraction::SmaatElektronik
+        );
+        assert_eq!(Fraction::from_name("Garden waste"), Fraction::Haveaffald);
+        assert_eq!(
+            Fraction::from_name("Juletræer"),
+            Fraction::Other("Juletræer".to_string())
+        );
+    }
+
+    #[test]
+    fn catalogue_fractions_have_slugs_names_and_icons() {
+        let fraction = Fraction::MadOgDrikkekartoner;
+
+        assert_eq!(fraction.slug(), "mad_og_drikkekartoner");
+        assert_eq!(fraction.name(Language::Da), "Mad- og drikkekartoner");
+        assert_eq!(fraction.name(Language::En), "Food and drink cartons");
+        assert_eq!(fraction.icon(), "mdi:food-takeout-box");
+
+        let slugs: HashSet<_> = CATALOGUE.iter().map(Fraction::slug).collect();
+        assert_eq!(slugs.len(), CATALOGUE.len());
+        assert!(CATALOGUE
+            .iter()
+            .all(|x| x.slug() == Fraction::from_name(x.name(Language::Da)).slug()));
+    }
+
+    #[test]
+    fn other_fractions_keep_their_name() {
+        let fraction = Fraction::from_name("Plast/metal");
+
+        assert_eq!(fraction.slug(), "plast_metal");
+        assert_eq!(fraction.name(Language::En), "Plast/metal");
+        assert_eq!(fraction.icon(), DEFAULT_ICON);
+    }
+
+    #[test]
+    fn slugs_are_valid_object_ids() {
+        assert_eq!(slugify("Mad- og drikkekartoner"), "mad_og_drikkekartoner");
+        assert_eq!(slugify("Mad_ og drikkekartoner"), "mad_og_drikkekartoner");
+        assert_eq!(slugify("Småt elektronik"), "smaat_elektronik");
+        assert_eq!(slugify("ÆØÅ æøå"), "aeoeaa_aeoeaa");
+        assert_eq!(slugify("  Glas/Metal (2)  "), "glas_metal_2");
+        assert_eq!(slugify("Café"), "cafe");
+        assert_eq!(slugify("---"), "unknown");
+    }
+
+    fn container(name: &str, date: &str) -> Container {
+        Container::new(
+            name.to_string(),
+            date.parse().unwrap(),
+            "68460".to_string(),
+            "Kongevejen 100, 8000 Aarhus C".to_string(),
+        )
+    }
+
+    fn fraction(name: Option<&str>, icon: Option<&str>, hide: bool) -> FractionConfig {
+        FractionConfig {
+            name: name.map(str::to_string),
+            icon: icon.map(str::to_string),
+            hide,
+        }
+    }
+
+    fn fractions() -> BTreeMap<String, FractionConfig> {
+        BTreeMap::from([
+            (
+                "Mad- og drikkekartoner".to_string(),
+                fraction(Some("Kartoner"), None, false),
+            ),
+            (
+                "Pap".to_string(),
+                fraction(Some("Papir og pap"), Some("mdi:package-variant"), false),
+            ),
+            (
+                "Papir".to_string(),
+                fraction(Some("Papir og pap"), Some("mdi:newspaper"), false),
+            ),
+            ("Tekstiler".to_string(), fraction(None, None, true)),
+            (
+                "Glas".to_string(),
+                fraction(None, Some("mdi:bottle-wine"), false),
+            ),
+        ])
+    }
+
+    #[test]
+    fn renames_merges_and_hides_fractions() {
+        let fractions = fractions();
+        let mapping = FractionMapping::new(&fractions);
+
+        let actual = FractionSchedule::group(mapping.apply(vec![
+            container("Papir", "2024-05-09"),
+            container("Mad- og drikkekartoner", "2024-04-18"),
+            container("Pap", "2024-05-02"),
+            container("Tekstiler", "2024-04-18"),
+            container("Restaffald", "2024-04-26"),
+        ]));
+
+        let actual: Vec<_> = actual
+            .iter()
+            .map(|x| (x.name.as_str(), x.next().to_string()))
+            .collect();
+        assert_eq!(
+            actual,
+            vec![
+                ("Papir og pap", "2024-05-02".to_string()),
+                ("Kartoner", "2024-04-18".to_string()),
+                ("Restaffald", "2024-04-26".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn assigns_configured_icons() {
+        let fractions = fractions();
+        let mapping = FractionMapping::new(&fractions);
+        let mut schedules = FractionSchedule::group(mapping.apply(vec![
+            container("Glas", "2024-04-18"),
+            container("Papir", "2024-05-09"),
+            container("Restaffald", "2024-04-26"),
+        ]));
+
+        mapping.assign_icons(&mut schedules);
+
+        let actual: Vec<_> = schedules
+            .iter()
+            .map(|x| (x.name.as_str(), x.icon.as_deref()))
+            .collect();
+        assert_eq!(
+            actual,
+            vec![
+                ("Glas", Some("mdi:bottle-wine")),
+                ("Papir og pap", Some("mdi:package-variant")),
+                ("Restaffald", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn icon_of_the_new_name_wins_over_merged_ones() {
+        let mut fractions = fractions();
+        fractions.insert(
+            "Papir og pap".to_string(),
+            fraction(None, Some("mdi:recycle"), false),
+        );
+        let mapping = FractionMapping::new(&fractions);
+        let mut schedules = FractionSchedule::group(mapping.apply(vec![
+            container("Pap", "2024-05-02"),
+            container("Papir", "2024-05-09"),
+        ]));
+
+        mapping.assign_icons(&mut schedules);
+
+        assert_eq!(schedules.len(), 1);
+        assert_eq!(schedules[0].icon.as_deref(), Some("mdi:recycle"));
+    }
+
+    #[test]
+    fn keeps_configured_names_untranslated() {
+        let fractions = BTreeMap::from([(
+            "Grene og blade".to_string(),
+            fraction(Some("Garden waste"), None, false),
+        )]);
+        let mapping = FractionMapping::new(&fractions);
+        let mut schedules = FractionSchedule::group(mapping.apply(vec![
+            container("Grene og blade", "2024-04-18"),
+            container("Restaffald", "2024-04-19"),
+        ]));
+
+        mapping.mark_renamed(&mut schedules);
+
+        let actual: Vec<_> = schedules
+            .iter()
+            .map(|x| x.display_name(Language::Da))
+            .collect();
+        assert_eq!(actual, vec!["Garden waste", "Restaffald"]);
+        assert_eq!(schedules[0].fraction, Fraction::Haveaffald);
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use rumqttc::{
+    v5::{
+        self,
+        mqttbytes::v5::{PubAck, PubAckReason, PublishProperties},
+    },
+    AsyncClient, Event, EventLoop, MqttOptions, Outgoing, Packet, QoS,
+};
+use tokio::sync::Notify;
+
+use crate::error::Error;
+use crate::settings::{MQTTConfig, MqttVersion};
+
+/// User property carrying the id of the synchronization run that published a message.
+const RUN_ID_PROPERTY: &str = "run_id";
+
+/// The connection settings for the configured protocol version.
+#[allow(clippy::large_enum_variant)]
+pub enum MqttClientOptions {
+    V311(MqttOptions),
+    V5(v5::MqttOptions),
+}
+
+impl TryFrom<MQTTConfig> for MqttClientOptions {
+    type Error = Error;
+
+    fn try_from(val: MQTTConfig) -> Result<Self, Error> {
+        match val.version {
+            MqttVersion::V311 => val.try_into().map(MqttClientOptions::V311),
+            MqttVersion::V5 => val.try_into().map(MqttClientOptions::V5),
+        }
+    }
+}
+
+/// Publishes with QoS 1 over MQTT 3.1.1 or 5.
+///
+/// With MQTT 5 every message carries the run id as user property, and states expire from the
+/// broker when they are not refreshed in time. Both are left out with MQTT 3.1.1.
+pub struct MqttClient {
+    inner: Inner,
+    run_id: Option<String>,
+    state_expiry: Option<Duration>,
+    shared: Arc<Shared>,
+}
+
+enum Inner {
+    V311(AsyncClient),
+    V5(v5::AsyncClient),
+}
+
+impl MqttClient {
+    /// Nothing is sent until the returned connection runs, at most `cap` messages are buffered
+    /// until it sends them.
+    pub fn new(options: MqttClientOptions, cap: usize) -> (Self, MqttConnection) {
+        let (inner, events) = match options {
+            MqttClientOptions::V311(options) => {
+                let (client, events) = AsyncClient::new(options, cap);
+                (Inner::V311(client), Events::V311(events))
+            }
+            MqttClientOptions::V5(options) => {
+                let (client, events) = v5::AsyncClient::new(options, cap);
+                (Inner::V5(client), Events::V5(events))
+            }
+        };
+        let shared = Arc::new(Shared::default());
+
+        let client = MqttClient {
+            inner,
+            run_id: None,
+            state_expiry: None,
+            shared: shared.clone(),
+        };
+        let connection = MqttConnection { events, shared };
+
+        (client, connection)
+    }
+
+    /// Tags every message with `run_id`, so messages of the same synchronization can be told apart.
+    pub fn with_run_id(mut self, run_id: impl Into<String>) -> Self {
+        self.run_id = Some(run_id.into());
+        self
+    }
+
+    /// Lets the broker drop states that were not refreshed within `expiry`.
+    pub fn with_state_expiry(mut self, expiry: Duration) -> Self {
+        self.state_expiry = Some(expiry);
+        self
+    }
+
+    pub async fn publish(
+        &self,
+        topic: impl Into<String>,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<(), Error> {
+        self.send(topic.into(), retain, payload.into(), None).await
+    }
+
+    /// Publishes a retained state, which expires unless it is published again in time.
+    pub async fn publish_state(
+        &self,
+        topic: impl Into<String>,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<(), Error> {
+        self.send(topic.into(), true, payload.into(), self.state_expiry)
+            .await
+    }
+
+    async fn send(
+        &self,
+        topic: String,
+        retain: bool,
+        payload: Vec<u8>,
+        expiry: Option<Duration>,
+    ) -> Result<(), Error> {
+        self.shared.tracking().pending.push_back(topic.clone());
+
+        let sent = match &self.inner {
+            Inner::V311(client) => client
+                .publish(topic, QoS::AtLeastOnce, retain, payload)
+                .await
+                .map_err(Error::from),
+            Inner::V5(client) => {
+                let properties = PublishProperties {
+                    message_expiry_interval: expiry
+                        .map(|x| u32::try_from(x.as_secs()).unwrap_or(u32::MAX)),
+                    user_properties: self
+                        .run_id
+                        .iter()
+                        .map(|x| (RUN_ID_PROPERTY.to_string(), x.clone()))
+                        .collect(),
+                    ..Default::default()
+                };
+                client
+                    .publish_with_properties(
+                        topic,
+                        v5::mqttbytes::QoS::AtLeastOnce,
+                        retain,
+                        payload,
+                        properties,
+                    )
+                    .await
+                    .map_err(Error::from)
+            }
+        };
+
+        if sent.is_err() {
+            self.shared.tracking().pending.pop_back();
+        }
+
+        sent
+    }
+
+    /// Waits for the broker to acknowledge everything published and disconnects.
+    ///
+    /// Returns right away when the connection already ended, [`MqttConnection::run`] tells why.
+    pub async fn close(self) -> Result<(), Error> {
+        loop {
+            {
+                let tracking = self.shared.tracking();
+                if tracking.finished {
+                    return Ok(());
+                }
+                if tracking.pending.is_empty() && tracking.in_flight.is_empty() {
+                    break;
+                }
+            }
+            self.shared.changed.notified().await;
+        }
+
+        self.inner.disconnect().await
+    }
+}
+
+impl Inner {
+    async fn disconnect(&self) -> Result<(), Error> {
+        match self {
+            Inner::V311(client) => client.disconnect().await.map_err(Error::from),
+            Inner::V5(client) => client.disconnect().await.map_err(Error::from),
+        }
+    }
+}
+
+/// Sends what the client publishes, see [`MqttConnection::run`].
+pub struct MqttConnection {
+    events: Events,
+    shared: Arc<Shared>,
+}
+
+/// What the connection did with the published messages, so the client can tell when it is done.
+#[derive(Default)]
+struct Shared {
+    tracking: Mutex<Tracking>,
+    /// Notified whenever the tracking changed.
+    changed: Notify,
+}
+
+#[derive(Default)]
+struct Tracking {
+    /// Topics of the messages the connection did not send yet, in the order they were published.
+    pending: VecDeque<String>,
+    /// Topics of the messages sent but not acknowledged yet, by packet id.
+    in_flight: HashMap<u16, String>,
+    /// The connection ended, nothing is sent or acknowledged anymore.
+    finished: bool,
+}
+
+impl Shared {
+    fn tracking(&self) -> MutexGuard<'_, Tracking> {
+        self.tracking.lock().expect("Message tracking poisoned")
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+enum Events {
+    V311(EventLoop),
+    V5(v5::EventLoop),
+}
+
+/// What happened on the connection, the same for both protocol versions.
+enum Progress {
+    Sent(u16),
+    Acknowledged(u16, Option<Rejection>),
+    Disconnected,
+    Other,
+}
+
+struct Rejection {
+    reason: PubAckReason,
+    description: Option<String>,
+}
+
+impl MqttConnection {
+    /// Sends what the client publishes until [`MqttClient::close`] disconnects.
+    ///
+    /// Has to run concurrently with the publishing, e.g. spawned, as the client only buffers a
+    /// few messages. Messages the broker rejected (MQTT 5 only) are returned, the connection
+    /// failing is an error.
+    pub async fn run(mut self) -> Result<Vec<Error>, Error> {
+        let outcome = self.send().await;
+
+        self.shared.tracking().finished = true;
+        self.shared.changed.notify_one();
+
+        outcome
+    }
+
+    async fn send(&mut self) -> Result<Vec<Error>, Error> {
+        let mut rejected = Vec::new();
+        let mut disconnected = false;
+        loop {
+            //iterate the connection untill we hit the error generated by disconnect(),
+            //any error before the disconnect went out means the broker was never reached properly
+            let progress = match self.events.poll().await {
+                Ok(progress) => progress,
+                Err(_) if disconnected => return Ok(rejected),
+                Err(x) => return Err(x),
+            };
+
+            let mut tracking = self.shared.tracking();
+            match progress {
+                Progress::Sent(pkid) => {
+                    if let Some(topic) = tracking.pending.pop_front() {
+                        tracking.in_flight.insert(pkid, topic);
+                    }
+                }
+                Progress::Acknowledged(pkid, rejection) => {
+                    let topic = tracking.in_flight.remove(&pkid).unwrap_or_default();
+                    if let Some(Rejection {
+                        reason,
+                        description,
+                    }) = rejection
+                    {
+                        rejected.push(Error::PublishRejected {
+                            topic,
+                            reason,
+                            description,
+                        });
+                    }
+                }
+                Progress::Disconnected => disconnected = true,
+                Progress::Other => continue,
+            }
+            drop(tracking);
+            self.shared.changed.notify_one();
+        }
+    }
+}
+
+impl Events {
+    async fn poll(&mut self) -> Result<Progress, Error> {
+        let progress = match self {
+            Events::V311(events) => match events.poll().await? {
+                Event::Outgoing(Outgoing::Publish(pkid)) => Progress::Sent(pkid),
+                Event::Outgoing(Outgoing::Disconnect) => Progress::Disconnected,
+                Event::Incoming(Packet::PubAck(ack)) => Progress::Acknowledged(ack.pkid, None),
+                _ => Progress::Other,
+            },
+            Events::V5(events) => match events.poll().await? {
+                v5::Event::Outgoing(Outgoing::Publish(pkid)) => Progress::Sent(pkid),
+                v5::Event::Outgoing(Outgoing::Disconnect) => Progress::Disconnected,
+                v5::Event::Incoming(v5::Incoming::PubAck(ack)) => {
+                    Progress::Acknowledged(ack.pkid, rejection(ack))
+                }
+                _ => Progress::Other,
+            },
+        };
+
+        Ok(progress)
+    }
+}
+
+fn rejection(ack: PubAck) -> Option<Rejection> {
+    match ack.reason {
+        PubAckReason::Success | PubAckReason::NoMatchingSubscribers => None,
+        reason => Some(Rejection {
+            reason,
+            description: ack.properties.and_then(|x| x.reason_string),
+        }),
+    }
+}
+
+/// The reason code of `reason` as sent by the broker, e.g. 0x87 for NotAuthorized.
+pub(crate) fn reason_code(reason: PubAckReason) -> u8 {
+    match reason {
+        PubAckReason::Success => 0x00,
+        PubAckReason::NoMatchingSubscribers => 0x10,
+        PubAckReason::UnspecifiedError => 0x80,
+        PubAckReason::ImplementationSpecificError => 0x83,
+        PubAckReason::NotAuthorized => 0x87,
+        PubAckReason::TopicNameInvalid => 0x90,
+        PubAckReason::PacketIdentifierInUse => 0x91,
+        PubAckReason::QuotaExceeded => 0x97,
+        PubAckReason::PayloadFormatInvalid => 0x99,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rumqttc::v5::mqttbytes::v5::PubAckProperties;
+
+    fn config(version: MqttVersion) -> MQTTConfig {
+        MQTTConfig {
+            host: "localhost".to_string(),
+            version,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn options_follow_version() {
+        assert!(matches!(
+            config(MqttVersion::V311).try_into(),
+            Ok(MqttClientOptions::V311(_))
+        ));
+        assert!(matches!(
+            config(MqttVersion::V5).try_into(),
+            Ok(MqttClientOptions::V5(_))
+        ));
+    }
+
+    #[test]
+    fn accepted_messages_are_no_rejection() {
+        for reason in [PubAckReason::Success, PubAckReason::NoMatchingSubscribers] {
+            let ack = PubAck {
+                pkid: 1,
+                reason,
+                properties: None,
+            };
+            assert!(rejection(ack).is_none());
+        }
+    }
+
+    #[test]
+    fn rejection_shows_reason_code() {
+        let Rejection {
+            reason,
+            description,
+        } = rejection(PubAck {
+            pkid: 1,
+            reason: PubAckReason::NotAuthorized,
+            properties: Some(PubAckProperties {
+                reason_string: Some("ACL denied".to_string()),
+                user_properties: Vec::new(),
+            }),
+        })
+        .unwrap();
+
+        let error = Error::PublishRejected {
+            topic: "garbage_bin/affaldvarme/restaffald/status".to_string(),
+            reason,
+            description,
+        };
+        assert_eq!(
+            error.to_string(),
+            "Broker rejected the message to garbage_bin/affaldvarme/restaffald/status: NotAuthorized (0x87), ACL denied"
+        );
+    }
+}
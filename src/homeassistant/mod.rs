@@ -1,83 +1,223 @@
-use std::collections::HashMap;
+mod client;
+mod tls;
+mod topics;
 
-use crate::mitaffald::Container;
-use crate::settings::MQTTConfig;
-use rumqttc::{AsyncClient, LastWill, MqttOptions};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+use crate::changes::ScheduleChange;
+use crate::error::Error;
+use crate::fractions::slugify;
+use crate::provider::FractionSchedule;
+use crate::recurrence::Recurrence;
+use crate::settings::{Language, MQTTConfig, MqttTransport};
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc, Weekday};
+use rumqttc::{v5, LastWill, MqttOptions, Transport};
 use serde_json::json;
 
-const HA_AVAILABILITY_TOPIC: &str = "garbage_bin/availability";
+pub(crate) use self::client::reason_code;
+pub use self::client::{MqttClient, MqttClientOptions, MqttConnection};
+pub use self::topics::Topics;
+
 const HA_PAYLOAD_AVAILABLE: &str = "online";
 const HA_PAYLOAD_NOT_AVAILABLE: &str = "offline";
-const HA_DEVICE_NAME: &str = "Affaldvarme Device";
-
-impl From<MQTTConfig> for MqttOptions {
-    fn from(val: MQTTConfig) -> Self {
-        let mut config = MqttOptions::new(val.client_id, val.host, val.port);
-        config
-            .set_credentials(val.username, val.password)
-            .set_last_will(LastWill::new(
-                HA_AVAILABILITY_TOPIC,
-                HA_PAYLOAD_NOT_AVAILABLE,
-                rumqttc::QoS::AtLeastOnce,
-                true,
-            ));
 
-        config
+impl TryFrom<MQTTConfig> for MqttOptions {
+    type Error = Error;
+
+    fn try_from(val: MQTTConfig) -> Result<Self, Error> {
+        let availability_topic = Topics::new(&val).availability();
+        let (broker, transport) = broker(&val).map_err(Error::InvalidTls)?;
+
+        let mut config = MqttOptions::new(val.client_id, broker, val.port);
+        // some brokers refuse empty credentials
+        if !val.username.is_empty() {
+            config.set_credentials(val.username, val.password);
+        }
+        config.set_last_will(LastWill::new(
+            availability_topic,
+            HA_PAYLOAD_NOT_AVAILABLE,
+            rumqttc::QoS::AtLeastOnce,
+            true,
+        ));
+
+        config.set_transport(transport);
+
+        Ok(config)
+    }
+}
+
+impl TryFrom<MQTTConfig> for v5::MqttOptions {
+    type Error = Error;
+
+    fn try_from(val: MQTTConfig) -> Result<Self, Error> {
+        let availability_topic = Topics::new(&val).availability();
+        let (broker, transport) = broker(&val).map_err(Error::InvalidTls)?;
+
+        let mut config = v5::MqttOptions::new(val.client_id, broker, val.port);
+        if !val.username.is_empty() {
+            config.set_credentials(val.username, val.password);
+        }
+        config.set_last_will(v5::mqttbytes::v5::LastWill::new(
+            availability_topic,
+            HA_PAYLOAD_NOT_AVAILABLE,
+            v5::mqttbytes::QoS::AtLeastOnce,
+            true,
+            None,
+        ));
+
+        config.set_transport(transport);
+
+        Ok(config)
     }
 }
 
+/// Where and how to connect, fails with the reason when the TLS settings are not usable.
+fn broker(config: &MQTTConfig) -> Result<(String, Transport), String> {
+    let tls = || tls::configuration(&config.tls.clone().unwrap_or_default());
+    // WebSocket connections are made to an URL rather than to a host
+    Ok(match config.transport() {
+        MqttTransport::Tcp => (config.host.clone(), Transport::tcp()),
+        MqttTransport::Tls => (config.host.clone(), Transport::tls_with_config(tls()?)),
+        MqttTransport::Ws => (websocket_url("ws", config), Transport::ws()),
+        MqttTransport::Wss => (
+            websocket_url("wss", config),
+            Transport::wss_with_config(tls()?),
+        ),
+    })
+}
+
+fn websocket_url(scheme: &str, config: &MQTTConfig) -> String {
+    format!(
+        "{}://{}:{}/{}",
+        scheme,
+        config.host,
+        config.port,
+        config.path.trim_start_matches('/')
+    )
+}
+
 pub struct CreatedState;
 pub struct InitializedState {
+    /// Keyed by object id, the same id is never published twice.
     sensors: HashMap<String, HASensor>,
+    stale_since: Option<DateTime<Utc>>,
 }
 
 pub struct HADevice<T> {
+    identity: DeviceIdentity,
+    upcoming_days: u32,
+    /// How many days ahead pickups are predicted, not at all without.
+    predict_days: Option<u32>,
+    language: Language,
     state: T,
 }
 
-impl Default for HADevice<CreatedState> {
-    fn default() -> Self {
+/// Distinguishes the devices (and therefore their topics) of multiple addresses.
+#[derive(Clone)]
+struct DeviceIdentity {
+    id: String,
+    name: String,
+    topics: Topics,
+}
+
+impl DeviceIdentity {
+    fn identifier(&self) -> String {
+        format!("ha_{}", self.id)
+    }
+
+    fn availability_topic(&self) -> String {
+        self.topics.device_availability(&self.id)
+    }
+
+    fn changes_topic(&self) -> String {
+        self.topics.changes(&self.id)
+    }
+
+    fn availability(&self) -> serde_json::Value {
+        json!([
+            {
+                "topic": self.topics.availability(),
+                "payload_available": HA_PAYLOAD_AVAILABLE,
+                "payload_not_available": HA_PAYLOAD_NOT_AVAILABLE,
+            },
+            {
+                "topic": self.availability_topic(),
+                "payload_available": HA_PAYLOAD_AVAILABLE,
+                "payload_not_available": HA_PAYLOAD_NOT_AVAILABLE,
+            }
+        ])
+    }
+}
+
+impl HADevice<CreatedState> {
+    pub fn new(id: impl Into<String>, name: impl Into<String>, topics: Topics) -> Self {
         HADevice {
+            identity: DeviceIdentity {
+                id: id.into(),
+                name: name.into(),
+                topics,
+            },
+            upcoming_days: 0,
+            predict_days: None,
+            language: Language::default(),
             state: CreatedState,
         }
     }
-}
 
-impl HADevice<CreatedState> {
+    /// Lists the pickups of the next `days` days in the `upcoming` attribute of every sensor.
+    pub fn with_upcoming_days(mut self, days: u32) -> Self {
+        self.upcoming_days = days;
+        self
+    }
+
+    /// Continues the regular rhythm of every fraction in `upcoming` once its planned pickups run
+    /// out, for the next `days` days regardless of the upcoming days. The extra pickups are
+    /// marked with `predicted: true`.
+    pub fn with_predictions(mut self, days: Option<u32>) -> Self {
+        self.predict_days = days;
+        self
+    }
+
+    /// Names the sensors of known fractions in `language`.
+    pub fn with_language(mut self, language: Language) -> Self {
+        self.language = language;
+        self
+    }
+
     pub async fn initialize(
         mut self,
-        client: &mut AsyncClient,
-    ) -> Result<HADevice<InitializedState>, String> {
-        self.register_device(client)
-            .await
-            .map_err(|e| e.to_string())?;
+        client: &mut MqttClient,
+    ) -> Result<HADevice<InitializedState>, Error> {
+        self.register_device(client).await?;
 
-        self.register_device_availability(client)
-            .await
-            .map_err(|e| e.to_string())?;
+        self.register_change_events(client).await?;
+
+        self.register_device_availability(client).await?;
 
         Ok(HADevice {
+            identity: self.identity,
+            upcoming_days: self.upcoming_days,
+            predict_days: self.predict_days,
+            language: self.language,
             state: InitializedState {
                 sensors: HashMap::new(),
+                stale_since: None,
             },
         })
     }
 
-    async fn register_device(
-        &mut self,
-        client: &mut AsyncClient,
-    ) -> Result<(), rumqttc::ClientError> {
+    async fn register_device(&mut self, client: &mut MqttClient) -> Result<(), Error> {
+        let identifier = self.identity.identifier();
         let payload = json!(
             {
-                "unique_id": "ha_affaldvarme_device",
-                "name": "Affaldvarme Device",
-                "state_topic": HA_AVAILABILITY_TOPIC,
-                "availability_topic": HA_AVAILABILITY_TOPIC,
-                "payload_available": HA_PAYLOAD_AVAILABLE,
-                "payload_not_available": HA_PAYLOAD_NOT_AVAILABLE,
+                "unique_id": format!("{}_device", identifier),
+                "name": self.identity.name,
+                "state_topic": self.identity.availability_topic(),
+                "availability": self.identity.availability(),
+                "availability_mode": "all",
                 "device": {
-                    "identifiers": ["ha_affaldvarme"],
-                    "name": HA_DEVICE_NAME,
+                    "identifiers": [identifier],
+                    "name": self.identity.name,
                     "sw_version": "1.0",
                     "model": "Standard",
                     "manufacturer": "Your humble rust developer"
@@ -87,22 +227,57 @@ impl HADevice<CreatedState> {
 
         client
             .publish(
-                "homeassistant/sensor/ha_affaldvarme_device/config",
-                rumqttc::QoS::AtLeastOnce,
+                self.identity
+                    .topics
+                    .discovery("sensor", &format!("{}_device", identifier)),
                 true,
                 serde_json::to_string(&payload).expect("Failed to serialize"),
             )
             .await
     }
 
-    async fn register_device_availability(
-        &mut self,
-        client: &mut AsyncClient,
-    ) -> Result<(), rumqttc::ClientError> {
+    /// An event entity that fires whenever a pickup is moved, added or removed.
+    async fn register_change_events(&mut self, client: &mut MqttClient) -> Result<(), Error> {
+        let identifier = self.identity.identifier();
+        let payload = json!(
+            {
+                "unique_id": format!("{}_schedule_changes", identifier),
+                "name": "Schedule changes",
+                "state_topic": self.identity.changes_topic(),
+                "event_types": ["moved", "added", "removed"],
+                "availability": self.identity.availability(),
+                "availability_mode": "all",
+                "device": {
+                    "identifiers": [identifier],
+                    "name": self.identity.name,
+                },
+                "icon": "mdi:calendar-alert"
+            }
+        );
+
+        client
+            .publish(
+                self.identity
+                    .topics
+                    .discovery("event", &format!("{}_schedule_changes", identifier)),
+                true,
+                serde_json::to_string(&payload).expect("Failed to serialize"),
+            )
+            .await
+    }
+
+    async fn register_device_availability(&mut self, client: &mut MqttClient) -> Result<(), Error> {
         client
             .publish(
-                HA_AVAILABILITY_TOPIC,
-                rumqttc::QoS::AtLeastOnce,
+                self.identity.topics.availability(),
+                true,
+                HA_PAYLOAD_AVAILABLE,
+            )
+            .await?;
+
+        client
+            .publish(
+                self.identity.availability_topic(),
                 true,
                 HA_PAYLOAD_AVAILABLE,
             )
@@ -111,96 +286,288 @@ impl HADevice<CreatedState> {
 }
 
 impl HADevice<InitializedState> {
+    /// Flags everything reported from now on as coming from data fetched at `fetched_at`.
+    pub fn mark_stale(&mut self, fetched_at: DateTime<Utc>) {
+        self.state.stale_since = Some(fetched_at);
+    }
+
+    /// Makes all entities of the device unavailable in Home Assistant.
+    pub async fn mark_unavailable(&mut self, client: &mut MqttClient) -> Result<(), Error> {
+        client
+            .publish(
+                self.identity.availability_topic(),
+                true,
+                HA_PAYLOAD_NOT_AVAILABLE,
+            )
+            .await
+    }
+
+    /// Fires a change event per change. Events are not retained, Home Assistant would fire
+    /// them again on every restart.
+    pub async fn report_changes(
+        &mut self,
+        changes: &[ScheduleChange],
+        client: &mut MqttClient,
+    ) -> Result<(), Error> {
+        for change in changes {
+            client
+                .publish(
+                    self.identity.changes_topic(),
+                    false,
+                    serde_json::to_string(change).expect("Failed to serialize"),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Publishes every schedule of the device, each one as its own sensor.
     pub async fn report(
         &mut self,
-        container: Container,
-        client: &mut AsyncClient,
-    ) -> Result<(), String> {
-        let sensor_id = HASensor::generate_sensor_id(&container);
-        self.state
-            .sensors
-            .entry(sensor_id.clone())
-            .or_insert_with(|| HASensor::new(&container))
-            .report(container, client)
+        schedules: &[FractionSchedule],
+        today: NaiveDate,
+        client: &mut MqttClient,
+    ) -> Result<(), Error> {
+        let mut errors = Vec::new();
+        for (schedule, sensor_id) in schedules.iter().zip(sensor_ids(schedules)) {
+            if let Err(x) = self
+                .report_schedule(schedule, sensor_id, today, client)
+                .await
+            {
+                errors.push(x);
+            }
+        }
+
+        Error::collect(errors).map_or(Ok(()), Err)
+    }
+
+    async fn report_schedule(
+        &mut self,
+        schedule: &FractionSchedule,
+        sensor_id: String,
+        today: NaiveDate,
+        client: &mut MqttClient,
+    ) -> Result<(), Error> {
+        // computed here rather than by Home Assistant, the state is re-published at every local
+        // midnight so it never depends on when the message arrived
+        let days_until = (schedule.next() - today).num_days();
+        let recurrence = Recurrence::infer(&schedule.dates);
+        let mut attributes = json!({
+            "days_until": days_until,
+            "upcoming": upcoming(
+                &schedule.dates,
+                today,
+                self.upcoming_days,
+                recurrence.as_ref(),
+                self.predict_days,
+            ),
+        });
+        if let Some(recurrence) = recurrence {
+            attributes["interval_days"] = json!(recurrence.interval_days);
+            attributes["weekday"] = json!(weekday_name(recurrence.weekday()));
+        }
+        add_original_date(
+            &mut attributes,
+            schedule.next(),
+            &schedule.dates,
+            recurrence.as_ref(),
+        );
+
+        let sensor = match self.state.sensors.entry(sensor_id) {
+            Entry::Occupied(existing) => existing.into_mut(),
+            Entry::Vacant(v) => {
+                let name = if *v.key() == schedule.fraction.slug() {
+                    schedule.display_name(self.language).to_string()
+                } else {
+                    format!(
+                        "{} ({})",
+                        schedule.display_name(self.language),
+                        schedule.stand_name
+                    )
+                };
+                let sensor = HASensor::new(&self.identity, v.key().clone(), name);
+                v.insert(sensor)
+            }
+        };
+
+        sensor
+            .report(schedule, attributes, self.state.stale_since, client)
             .await
-            .map_err(|e| e.to_string())
+    }
+}
+
+/// The object id of every schedule's sensor, in the same order.
+///
+/// Ids are derived from the fraction slug, which is unique per stand as schedules are grouped on
+/// it. Where the same fraction is on several stands the first by stand id keeps the id, the
+/// others are prefixed with their stand id and numbered should that collide as well.
+/// The result does not depend on the order of `schedules`, so sensors keep their ids.
+fn sensor_ids(schedules: &[FractionSchedule]) -> Vec<String> {
+    let mut order: Vec<usize> = (0..schedules.len()).collect();
+    order.sort_by_key(|i| (&schedules[*i].stand_id, &schedules[*i].name));
+
+    let mut taken = HashSet::new();
+    let mut ids = vec![String::new(); schedules.len()];
+    for i in order {
+        let slug = schedules[i].fraction.slug();
+        let prefixed = slugify(&format!("{}_{}", schedules[i].stand_id, slug));
+
+        let id = [slug, prefixed.clone()]
+            .into_iter()
+            .chain((2..).map(|n| format!("{}_{}", prefixed, n)))
+            .find(|x| !taken.contains(x))
+            .expect("Numbered ids never run out");
+
+        taken.insert(id.clone());
+        ids[i] = id;
+    }
+
+    ids
+}
+
+/// The pickups before `today` + `days`, as ISO dates with their weekday. With `predict_days` the
+/// rhythm of `recurrence` is continued after the last of `dates`, until `today` + `predict_days`.
+fn upcoming(
+    dates: &[NaiveDate],
+    today: NaiveDate,
+    days: u32,
+    recurrence: Option<&Recurrence>,
+    predict_days: Option<u32>,
+) -> serde_json::Value {
+    let until = today + Days::new(days.into());
+
+    let planned = dates.iter().take_while(|date| **date < until).map(|date| {
+        let mut pickup = json!({
+            "date": date.format("%Y-%m-%d").to_string(),
+            "weekday": weekday_name(date.weekday()),
+        });
+        add_original_date(&mut pickup, *date, dates, recurrence);
+        pickup
+    });
+
+    let predicted = recurrence
+        .zip(predict_days)
+        .zip(dates.last())
+        .into_iter()
+        .flat_map(|((recurrence, days), last)| {
+            let until = today + Days::new(days.into());
+            recurrence
+                .after(*last)
+                .take_while(move |date| *date < until)
+        })
+        .map(|date| {
+            json!({
+                "date": date.format("%Y-%m-%d").to_string(),
+                "weekday": weekday_name(date.weekday()),
+                "moved": false,
+                "predicted": true,
+            })
+        });
+
+    planned.chain(predicted).collect()
+}
+
+/// The English name of `weekday`, e.g. "Thursday", the same in every attribute.
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+/// Flags `date` as `moved` when it is off the regular rhythm, with the date it would have been on.
+/// A pickup next to a regular one is an extra pickup rather than a moved one.
+fn add_original_date(
+    value: &mut serde_json::Value,
+    date: NaiveDate,
+    dates: &[NaiveDate],
+    recurrence: Option<&Recurrence>,
+) {
+    let original = recurrence
+        .and_then(|x| x.original_date(date))
+        .filter(|x| !dates.contains(x));
+
+    value["moved"] = json!(original.is_some());
+    if let Some(original) = original {
+        value["original_date"] = json!(original.format("%Y-%m-%d").to_string());
     }
 }
 
 struct HASensor {
+    identity: DeviceIdentity,
     container_id: String,
+    name: String,
     configure_topic: String,
     state_topic: String,
     is_initialized: bool,
 }
 
 impl HASensor {
-    pub fn new(container: &Container) -> Self {
-        let container_id: String = Self::generate_sensor_id(container);
-
+    fn new(identity: &DeviceIdentity, container_id: String, name: String) -> Self {
         Self {
-            configure_topic: format!(
-                "homeassistant/sensor/ha_affaldvarme_{}/config",
-                container_id
+            configure_topic: identity.topics.discovery(
+                "sensor",
+                &format!("{}_{}", identity.identifier(), container_id),
             ),
-            state_topic: format!("garbage_bin/{}/status", container_id),
+            state_topic: identity.topics.state(&identity.id, &container_id),
             is_initialized: false,
+            identity: identity.clone(),
             container_id,
+            name,
         }
     }
 
-    fn generate_sensor_id(container: &Container) -> String {
-        container
-            .name
-            .clone()
-            .chars()
-            .map(|c| if c.is_alphanumeric() { c } else { '_' })
-            .collect()
-    }
-
     async fn report(
         &mut self,
-        container: Container,
-        client: &mut AsyncClient,
-    ) -> Result<(), rumqttc::ClientError> {
-        self.register_sensor(&container, client).await?;
+        schedule: &FractionSchedule,
+        attributes: serde_json::Value,
+        stale_since: Option<DateTime<Utc>>,
+        client: &mut MqttClient,
+    ) -> Result<(), Error> {
+        self.register_sensor(schedule, client).await?;
 
-        self.register_sensor_value(&container, client).await
+        self.register_sensor_value(schedule, attributes, stale_since, client)
+            .await
     }
 
     async fn register_sensor(
         &mut self,
-        container: &Container,
-        client: &mut AsyncClient,
-    ) -> Result<(), rumqttc::ClientError> {
+        schedule: &FractionSchedule,
+        client: &mut MqttClient,
+    ) -> Result<(), Error> {
         if self.is_initialized {
             return Ok(());
         }
 
+        let identifier = self.identity.identifier();
         let payload = json!(
             {
-                "default_entity_id": format!("sensor.ha_affaldvarme_{}", self.container_id),
-                "unique_id": format!("ha_affaldvarme_{}", self.container_id),
-                "name": container.name,
+                "default_entity_id": format!("sensor.{}_{}", identifier, self.container_id),
+                "unique_id": format!("{}_{}", identifier, self.container_id),
+                "name": self.name,
                 "state_topic": self.state_topic,
                 "json_attributes_topic": self.state_topic,
-                "value_template": "{{ (strptime(value_json.next_empty, '%Y-%m-%d').date() - now().date()).days }}",
-                "availability_topic": HA_AVAILABILITY_TOPIC,
-                "payload_available": HA_PAYLOAD_AVAILABLE,
-                "payload_not_available": HA_PAYLOAD_NOT_AVAILABLE,
+                "value_template": "{{ value_json.days_until }}",
+                "availability": self.identity.availability(),
+                "availability_mode": "all",
                 "unit_of_measurement": "days",
                 "device": {
-                    "identifiers": ["ha_affaldvarme"],
-                    "name": HA_DEVICE_NAME,
+                    "identifiers": [identifier],
+                    "name": self.identity.name,
                 },
-                "icon": "mdi:recycle"
+                "icon": schedule.icon.as_deref().unwrap_or(schedule.fraction.icon())
             }
         );
 
         let publish_result = client
             .publish(
                 &self.configure_topic,
-                rumqttc::QoS::AtLeastOnce,
                 false,
                 serde_json::to_string(&payload).expect("Failed to serialize"),
             )
@@ -215,24 +582,276 @@ impl HASensor {
 
     async fn register_sensor_value(
         &self,
-        container: &Container,
-        client: &mut AsyncClient,
-    ) -> Result<(), rumqttc::ClientError> {
-        let payload = json!(
+        schedule: &FractionSchedule,
+        attributes: serde_json::Value,
+        stale_since: Option<DateTime<Utc>>,
+        client: &mut MqttClient,
+    ) -> Result<(), Error> {
+        let mut payload = json!(
             {
-                "name": container.name,
-                "next_empty": container.date.format("%Y-%m-%d").to_string(),
-                "last_update": chrono::Local::now().to_rfc3339()
+                "name": schedule.name,
+                "fraction": schedule.fraction.slug(),
+                "stand_id": schedule.stand_id,
+                "stand_name": schedule.stand_name,
+                "next_empty": schedule.next().format("%Y-%m-%d").to_string(),
+                "last_update": chrono::Local::now().to_rfc3339(),
+                "stale": stale_since.is_some()
             }
         );
 
+        if let (Some(payload), Some(attributes)) = (payload.as_object_mut(), attributes.as_object())
+        {
+            payload.extend(attributes.clone());
+        }
+
+        if let Some(fetched_at) = stale_since {
+            payload["fetched_at"] = json!(fetched_at.to_rfc3339());
+            payload["data_age_hours"] = json!((Utc::now() - fetched_at).num_hours());
+        }
+
         client
-            .publish(
+            .publish_state(
                 &self.state_topic,
-                rumqttc::QoS::AtLeastOnce,
-                true,
                 serde_json::to_string(&payload).expect("Failed to serialize"),
             )
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::Container;
+
+    fn schedules(fractions: &[(&str, &str)]) -> Vec<FractionSchedule> {
+        FractionSchedule::group(
+            fractions
+                .iter()
+                .map(|(stand_id, name)| {
+                    Container::new(
+                        name.to_string(),
+                        "2024-04-18".parse().unwrap(),
+                        stand_id.to_string(),
+                        format!("Stand {}", stand_id),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn mqtt_config(transport: MqttTransport) -> MQTTConfig {
+        MQTTConfig {
+            host: "broker.local".to_string(),
+            port: 8080,
+            transport: Some(transport),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn connects_to_websocket_url() {
+        let options: MqttOptions = mqtt_config(MqttTransport::Ws).try_into().unwrap();
+
+        assert_eq!(
+            options.broker_address(),
+            ("ws://broker.local:8080/mqtt".to_string(), 8080)
+        );
+        assert!(matches!(options.transport(), Transport::Ws));
+    }
+
+    #[test]
+    fn connects_to_host_over_tcp() {
+        let options: MqttOptions = mqtt_config(MqttTransport::Tcp).try_into().unwrap();
+
+        assert_eq!(options.broker_address(), ("broker.local".to_string(), 8080));
+        assert!(matches!(options.transport(), Transport::Tcp));
+    }
+
+    #[test]
+    fn sends_credentials_only_when_set() {
+        let options: MqttOptions = mqtt_config(MqttTransport::Tcp).try_into().unwrap();
+        assert_eq!(options.credentials(), None);
+
+        let config = MQTTConfig {
+            username: "home".to_string(),
+            password: "secret".to_string(),
+            ..mqtt_config(MqttTransport::Tcp)
+        };
+        let options: v5::MqttOptions = config.try_into().unwrap();
+        assert!(options
+            .credentials()
+            .is_some_and(|x| x.username == "home" && x.password == "secret"));
+    }
+
+    #[test]
+    fn sensor_ids_are_transliterated_slugs() {
+        let actual = sensor_ids(&schedules(&[
+            ("68460", "Mad- og drikkekartoner"),
+            ("68460", "Småt elektronik"),
+            ("68460", "Grønne Æbler"),
+        ]));
+
+        assert_eq!(
+            actual,
+            vec![
+                "mad_og_drikkekartoner",
+                "smaat_elektronik",
+                "groenne_aebler"
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_colliding_sensor_ids() {
+        let actual = sensor_ids(&schedules(&[
+            ("68461", "Restaffald"),
+            ("68460", "Restaffald"),
+            ("1", "Plast"),
+            ("0", "Plast"),
+            ("1", "1 Plast"),
+        ]));
+
+        assert_eq!(
+            actual,
+            vec![
+                "68461_restaffald",
+                "restaffald",
+                "1_plast_2",
+                "plast",
+                "1_plast",
+            ]
+        );
+    }
+
+    #[test]
+    fn sensor_ids_do_not_depend_on_order() {
+        let fractions = [
+            ("68461", "Restaffald"),
+            ("68460", "Restaffald"),
+            ("68460", "Plast/metal"),
+            ("68461", "Plast metal"),
+        ];
+        let ids = |fractions: &[(&str, &str)]| {
+            let schedules = schedules(fractions);
+            let mut ids: Vec<_> = schedules
+                .iter()
+                .map(|x| (x.stand_id.clone(), x.name.clone()))
+                .zip(sensor_ids(&schedules))
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        let mut reversed = fractions;
+        reversed.reverse();
+        assert_eq!(ids(&fractions), ids(&reversed));
+    }
+
+    #[test]
+    fn lists_upcoming_pickups_within_horizon() {
+        let dates: Vec<NaiveDate> = ["2024-04-18", "2024-05-02", "2024-05-16", "2024-05-30"]
+            .iter()
+            .map(|x| x.parse().unwrap())
+            .collect();
+
+        let actual = upcoming(&dates, "2024-04-17".parse().unwrap(), 30, None, None);
+
+        assert_eq!(
+            actual,
+            json!([
+                { "date": "2024-04-18", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-02", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-16", "weekday": "Thursday", "moved": false },
+            ])
+        );
+    }
+
+    #[test]
+    fn predicts_pickups_past_the_plan() {
+        let dates: Vec<NaiveDate> = ["2024-04-18", "2024-05-02", "2024-05-16"]
+            .iter()
+            .map(|x| x.parse().unwrap())
+            .collect();
+        let recurrence = Recurrence::infer(&dates);
+
+        let actual = upcoming(
+            &dates,
+            "2024-04-17".parse().unwrap(),
+            60,
+            recurrence.as_ref(),
+            Some(60),
+        );
+
+        assert_eq!(
+            actual,
+            json!([
+                { "date": "2024-04-18", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-02", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-16", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-30", "weekday": "Thursday", "moved": false, "predicted": true },
+                { "date": "2024-06-13", "weekday": "Thursday", "moved": false, "predicted": true },
+            ])
+        );
+    }
+
+    #[test]
+    fn predicts_further_than_the_upcoming_days() {
+        let dates: Vec<NaiveDate> = ["2024-04-18", "2024-05-02", "2024-05-16"]
+            .iter()
+            .map(|x| x.parse().unwrap())
+            .collect();
+        let recurrence = Recurrence::infer(&dates);
+
+        let actual = upcoming(
+            &dates,
+            "2024-04-17".parse().unwrap(),
+            30,
+            recurrence.as_ref(),
+            Some(60),
+        );
+
+        assert_eq!(
+            actual,
+            json!([
+                { "date": "2024-04-18", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-02", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-16", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-30", "weekday": "Thursday", "moved": false, "predicted": true },
+                { "date": "2024-06-13", "weekday": "Thursday", "moved": false, "predicted": true },
+            ])
+        );
+    }
+
+    #[test]
+    fn flags_moved_pickups() {
+        // Ascension Day 2024-05-09 moves the Thursday pickup to Saturday
+        let dates: Vec<NaiveDate> = [
+            "2024-04-25",
+            "2024-05-02",
+            "2024-05-11",
+            "2024-05-16",
+            "2024-05-23",
+        ]
+        .iter()
+        .map(|x| x.parse().unwrap())
+        .collect();
+        let recurrence = Recurrence::infer(&dates);
+
+        let actual = upcoming(
+            &dates,
+            "2024-04-24".parse().unwrap(),
+            21,
+            recurrence.as_ref(),
+            None,
+        );
+
+        assert_eq!(
+            actual,
+            json!([
+                { "date": "2024-04-25", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-02", "weekday": "Thursday", "moved": false },
+                { "date": "2024-05-11", "weekday": "Saturday", "moved": true, "original_date": "2024-05-09" },
+            ])
+        );
+    }
+}
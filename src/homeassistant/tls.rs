@@ -0,0 +1,206 @@
+use std::{fs, path::Path, sync::Arc};
+
+use rumqttc::TlsConfiguration;
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{aws_lc_rs, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use tracing::warn;
+
+use crate::settings::TlsConfig;
+
+/// The TLS settings as configured, fails with the reason when the files are not usable.
+/// The broker is verified against `ca_file` or the system roots.
+pub(super) fn configuration(config: &TlsConfig) -> Result<TlsConfiguration, String> {
+    let provider = Arc::new(aws_lc_rs::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|err| err.to_string())?;
+
+    let builder = if config.insecure_skip_verify {
+        warn!("The certificate of the MQTT broker is not verified");
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+    } else {
+        builder.with_root_certificates(roots(config.ca_file.as_deref())?)
+    };
+
+    let client_config = match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => builder
+            .with_client_auth_cert(certificates(cert)?, private_key(key)?)
+            .map_err(|err| err.to_string())?,
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err("client_cert and client_key have to be set together".to_string()),
+    };
+
+    Ok(TlsConfiguration::Rustls(Arc::new(client_config)))
+}
+
+fn roots(ca_file: Option<&Path>) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    let certificates = match ca_file {
+        Some(path) => certificates(path)?,
+        None => {
+            let native = rustls_native_certs::load_native_certs();
+            for err in native.errors {
+                warn!("Failed to load a system root certificate, error: {}", err);
+            }
+            native.certs
+        }
+    };
+
+    let (_, ignored) = roots.add_parsable_certificates(certificates);
+    if ignored > 0 {
+        warn!("Ignoring {} unusable CA certificate(s)", ignored);
+    }
+    if roots.is_empty() {
+        return Err("No CA certificates found".to_string());
+    }
+
+    Ok(roots)
+}
+
+fn certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certificates = CertificateDer::pem_slice_iter(&read(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    if certificates.is_empty() {
+        return Err(format!("{}: no certificates found", path.display()));
+    }
+
+    Ok(certificates)
+}
+
+fn private_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    PrivateKeyDer::from_pem_slice(&read(path)?)
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("Error reading {}: {}", path.display(), err))
+}
+
+/// Accepts any certificate, but still checks the handshake signatures.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn cert(name: &str) -> Option<PathBuf> {
+        Some(PathBuf::from("tests/certs").join(name))
+    }
+
+    #[test]
+    fn accepts_ca_and_client_certificate() {
+        let config = TlsConfig {
+            ca_file: cert("ca.crt"),
+            client_cert: cert("client.crt"),
+            client_key: cert("client.key"),
+            insecure_skip_verify: false,
+        };
+
+        assert!(matches!(
+            configuration(&config),
+            Ok(TlsConfiguration::Rustls(_))
+        ));
+    }
+
+    #[test]
+    fn accepts_any_broker_when_not_verifying() {
+        let config = TlsConfig {
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            configuration(&config),
+            Ok(TlsConfiguration::Rustls(_))
+        ));
+    }
+
+    #[test]
+    fn requires_client_certificate_and_key() {
+        let config = TlsConfig {
+            ca_file: cert("ca.crt"),
+            client_cert: cert("client.crt"),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            configuration(&config).err().as_deref(),
+            Some("client_cert and client_key have to be set together")
+        );
+    }
+
+    #[test]
+    fn reports_unusable_files() {
+        let config = TlsConfig {
+            ca_file: cert("missing.crt"),
+            ..Default::default()
+        };
+        assert!(configuration(&config)
+            .err()
+            .is_some_and(|x| x.starts_with("Error reading tests/certs/missing.crt")));
+
+        let config = TlsConfig {
+            ca_file: cert("client.key"),
+            ..Default::default()
+        };
+        assert_eq!(
+            configuration(&config).err().as_deref(),
+            Some("tests/certs/client.key: no certificates found")
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use crate::settings::MQTTConfig;
+
+/// Builds every topic that is published to, so they all follow the configured layout.
+///
+/// Discovery topics are `<discovery_prefix>/<component>/[<node_id>/]<object_id>/config`,
+/// everything else lives below `<base_topic>`.
+#[derive(Debug, Clone)]
+pub struct Topics {
+    discovery_prefix: String,
+    base_topic: String,
+    node_id: Option<String>,
+}
+
+impl Topics {
+    pub fn new(config: &MQTTConfig) -> Self {
+        Self {
+            discovery_prefix: config.discovery_prefix.trim_end_matches('/').to_string(),
+            base_topic: config.base_topic.trim_end_matches('/').to_string(),
+            node_id: config.node_id.clone().filter(|x| !x.is_empty()),
+        }
+    }
+
+    /// Availability of the service, set to offline by the last will when it disconnects.
+    pub fn availability(&self) -> String {
+        format!("{}/availability", self.base_topic)
+    }
+
+    pub fn device_availability(&self, device_id: &str) -> String {
+        format!("{}/{}/availability", self.base_topic, device_id)
+    }
+
+    pub fn changes(&self, device_id: &str) -> String {
+        format!("{}/{}/changes", self.base_topic, device_id)
+    }
+
+    pub fn state(&self, device_id: &str, sensor_id: &str) -> String {
+        format!("{}/{}/{}/status", self.base_topic, device_id, sensor_id)
+    }
+
+    /// Where Home Assistant picks up the configuration of an entity, `component` is e.g. "sensor".
+    pub fn discovery(&self, component: &str, object_id: &str) -> String {
+        match &self.node_id {
+            Some(node_id) => format!(
+                "{}/{}/{}/{}/config",
+                self.discovery_prefix, component, node_id, object_id
+            ),
+            None => format!(
+                "{}/{}/{}/config",
+                self.discovery_prefix, component, object_id
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(discovery_prefix: &str, base_topic: &str, node_id: Option<&str>) -> MQTTConfig {
+        MQTTConfig {
+            host: "localhost".to_string(),
+            discovery_prefix: discovery_prefix.to_string(),
+            base_topic: base_topic.to_string(),
+            node_id: node_id.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn all(topics: &Topics) -> Vec<String> {
+        vec![
+            topics.availability(),
+            topics.device_availability("affaldvarme"),
+            topics.changes("affaldvarme"),
+            topics.state("affaldvarme", "restaffald"),
+            topics.discovery("sensor", "ha_affaldvarme_device"),
+            topics.discovery("sensor", "ha_affaldvarme_restaffald"),
+            topics.discovery("event", "ha_affaldvarme_schedule_changes"),
+        ]
+    }
+
+    #[test]
+    fn default_topics() {
+        let topics = Topics::new(&config("homeassistant", "garbage_bin", None));
+
+        insta::assert_yaml_snapshot!(all(&topics));
+    }
+
+    #[test]
+    fn custom_prefix_and_base_topic() {
+        let topics = Topics::new(&config("ha-upstairs/", "home/waste", None));
+
+        insta::assert_yaml_snapshot!(all(&topics));
+    }
+
+    #[test]
+    fn custom_node_id() {
+        let topics = Topics::new(&config("homeassistant", "garbage_bin", Some("kongevejen")));
+
+        insta::assert_yaml_snapshot!(all(&topics));
+    }
+}
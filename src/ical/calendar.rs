@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz as Timezone;
+use rrule::{RRuleError, RRuleSet, Tz};
+use tracing::warn;
+
+/// The parts of a VCALENDAR needed to know when something is picked up.
+pub(super) struct Calendar {
+    /// `X-WR-CALNAME`, most feeds name the address or the municipality.
+    pub name: Option<String>,
+    pub events: Vec<Event>,
+}
+
+/// A VEVENT, pickups are whole days so every date and time is reduced to a local date.
+#[derive(Default)]
+pub(super) struct Event {
+    pub uid: Option<String>,
+    pub summary: String,
+    pub start: Option<NaiveDate>,
+    pub rrule: Option<String>,
+    pub rdates: Vec<NaiveDate>,
+    pub exdates: Vec<NaiveDate>,
+    /// Set when the event replaces a single occurrence of the recurring event with the same uid.
+    pub recurrence_id: Option<NaiveDate>,
+    pub cancelled: bool,
+}
+
+impl Calendar {
+    /// Fails with the reason when the text is not a calendar.
+    pub fn parse(text: &str, timezone: Timezone) -> Result<Self, String> {
+        let mut calendar = Calendar {
+            name: None,
+            events: Vec::new(),
+        };
+        let mut found = false;
+        let mut current: Option<Event> = None;
+        // Components nested in an event (e.g. VALARM) have properties of their own.
+        let mut nested = 0;
+
+        for line in unfold(text) {
+            let Some((name, value)) = split_property(&line) else {
+                continue;
+            };
+
+            match (name.to_ascii_uppercase().as_str(), current.as_mut()) {
+                ("BEGIN", None) if value.eq_ignore_ascii_case("VCALENDAR") => found = true,
+                ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                    current = Some(Event::default())
+                }
+                ("BEGIN", Some(_)) => nested += 1,
+                ("END", Some(_)) if nested > 0 => nested -= 1,
+                ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                    calendar.events.extend(current.take())
+                }
+                (_, Some(_)) if nested > 0 => {}
+                ("X-WR-CALNAME", None) => calendar.name = Some(unescape(value)),
+                ("UID", Some(event)) => event.uid = Some(value.to_string()),
+                ("SUMMARY", Some(event)) => event.summary = unescape(value),
+                ("DTSTART", Some(event)) => event.start = Some(parse_date(value, timezone)?),
+                ("RRULE", Some(event)) => event.rrule = Some(normalize_until(value, timezone)),
+                ("RDATE", Some(event)) => event.rdates.extend(parse_dates(value, timezone)?),
+                ("EXDATE", Some(event)) => event.exdates.extend(parse_dates(value, timezone)?),
+                ("RECURRENCE-ID", Some(event)) => {
+                    event.recurrence_id = Some(parse_date(value, timezone)?)
+                }
+                ("STATUS", Some(event)) => {
+                    event.cancelled = value.eq_ignore_ascii_case("CANCELLED")
+                }
+                _ => {}
+            }
+        }
+
+        if !found {
+            return Err("BEGIN:VCALENDAR is missing".into());
+        }
+
+        Ok(calendar)
+    }
+
+    /// The dates of every event within `from..until`, with recurring events expanded.
+    pub fn occurrences(&self, from: NaiveDate, until: NaiveDate) -> Vec<(&Event, Vec<NaiveDate>)> {
+        let mut replaced: HashMap<&str, Vec<NaiveDate>> = HashMap::new();
+        for event in &self.events {
+            if let (Some(uid), Some(date)) = (&event.uid, event.recurrence_id) {
+                replaced.entry(uid).or_default().push(date);
+            }
+        }
+
+        self.events
+            .iter()
+            .filter(|event| !event.cancelled)
+            .filter_map(|event| {
+                let Some(start) = event.start else {
+                    warn!("Ignoring event '{}' without a start date", event.summary);
+                    return None;
+                };
+
+                let recurring = match (&event.rrule, event.recurrence_id) {
+                    (Some(rrule), None) => {
+                        expand(start, rrule, from, until).unwrap_or_else(|err| {
+                            warn!("Ignoring invalid RRULE of '{}': {}", event.summary, err);
+                            Vec::new()
+                        })
+                    }
+                    _ => vec![start],
+                };
+                let replaced = event
+                    .uid
+                    .as_deref()
+                    .filter(|_| event.recurrence_id.is_none())
+                    .and_then(|uid| replaced.get(uid));
+
+                let mut dates: Vec<NaiveDate> = recurring
+                    .into_iter()
+                    .chain(event.rdates.iter().copied())
+                    .filter(|date| (from..until).contains(date))
+                    .filter(|date| !event.exdates.contains(date))
+                    .filter(|date| !replaced.is_some_and(|x| x.contains(date)))
+                    .collect();
+                dates.sort();
+                dates.dedup();
+
+                Some((event, dates))
+            })
+            .collect()
+    }
+}
+
+/// Expands the recurrence rule in UTC, which is safe as only whole days are involved.
+fn expand(
+    start: NaiveDate,
+    rrule: &str,
+    from: NaiveDate,
+    until: NaiveDate,
+) -> Result<Vec<NaiveDate>, RRuleError> {
+    let set: RRuleSet = format!("DTSTART:{}\nRRULE:{}", utc_midnight(start), rrule).parse()?;
+
+    let midnight = |date: NaiveDate| Tz::UTC.from_utc_datetime(&date.and_time(NaiveTime::MIN));
+
+    Ok(set
+        .after(midnight(from))
+        .before(midnight(until))
+        .all(u16::MAX)
+        .dates
+        .iter()
+        .map(|date| date.date_naive())
+        .collect())
+}
+
+/// `UNTIL` has to be in the same form as `DTSTART`, which is always UTC midnight here.
+fn normalize_until(rrule: &str, timezone: Timezone) -> String {
+    rrule
+        .split(';')
+        .map(|part| match part.split_once('=') {
+            Some((key, value)) if key.eq_ignore_ascii_case("UNTIL") => {
+                match parse_date(value, timezone) {
+                    Ok(date) => format!("UNTIL={}", utc_midnight(date)),
+                    Err(_) => part.to_string(),
+                }
+            }
+            _ => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn utc_midnight(date: NaiveDate) -> String {
+    date.format("%Y%m%dT000000Z").to_string()
+}
+
+/// A DATE or DATE-TIME value. UTC times are moved to `timezone`, local times keep their date.
+fn parse_date(value: &str, timezone: Timezone) -> Result<NaiveDate, String> {
+    let value = value.trim();
+    let date = match value.strip_suffix('Z') {
+        Some(utc) => NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|x| {
+                Utc.from_utc_datetime(&x)
+                    .with_timezone(&timezone)
+                    .date_naive()
+            }),
+        None => value
+            .get(..8)
+            .and_then(|x| NaiveDate::parse_from_str(x, "%Y%m%d").ok()),
+    };
+
+    date.ok_or_else(|| format!("Unexpected date '{}'", value))
+}
+
+fn parse_dates(value: &str, timezone: Timezone) -> Result<Vec<NaiveDate>, String> {
+    value.split(',').map(|x| parse_date(x, timezone)).collect()
+}
+
+/// Joins folded lines, a line starting with a space or tab continues the previous one.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+/// Splits `NAME;PARAM=...:VALUE` into name and value, parameters are not needed.
+fn split_property(line: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+
+    let name = line[..colon].split(';').next().unwrap_or_default();
+    Some((name.trim(), &line[colon + 1..]))
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n' | 'N') => result.push('\n'),
+            Some(x) => result.push(x),
+            None => {}
+        }
+    }
+
+    result.trim().to_string()
+}
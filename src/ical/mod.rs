@@ -0,0 +1,283 @@
+mod calendar;
+pub mod settings;
+
+use chrono::{Days, NaiveDate};
+use chrono_tz::Tz;
+use tokio::time::Instant;
+use tracing::info;
+
+use self::calendar::Calendar;
+use self::settings::{FractionRule, IcalConfig, IcalSource};
+use crate::{
+    clock,
+    error::Error,
+    provider::{Container, WasteProvider},
+    retry::RetryingClient,
+};
+
+/// Calendars have no stands, all pickups belong to this one.
+const STAND_ID: &str = "calendar";
+
+/// An iCalendar (.ics) feed as a [`WasteProvider`], as published by many municipalities.
+pub struct Ical {
+    config: IcalConfig,
+    timezone: Tz,
+    deadline: Instant,
+}
+
+impl Ical {
+    pub fn new(config: IcalConfig, timezone: Tz, deadline: Instant) -> Self {
+        Self {
+            config,
+            timezone,
+            deadline,
+        }
+    }
+}
+
+impl WasteProvider for Ical {
+    async fn get_containers(&self) -> Result<Vec<Container>, Error> {
+        get_containers(&self.config, self.timezone, self.deadline).await
+    }
+}
+
+/// The pickups of the configured calendar, downloaded before `deadline`.
+pub async fn get_containers(
+    config: &IcalConfig,
+    timezone: Tz,
+    deadline: Instant,
+) -> Result<Vec<Container>, Error> {
+    let calendar = Calendar::parse(&read_calendar(config, deadline).await?, timezone)
+        .map_err(Error::InvalidCalendar)?;
+    let today = clock::today(timezone);
+    let containers = containers(&calendar, config, today);
+
+    if containers.is_empty() {
+        return Err(Error::NoData);
+    }
+
+    Ok(containers)
+}
+
+async fn read_calendar(config: &IcalConfig, deadline: Instant) -> Result<String, Error> {
+    match &config.source {
+        IcalSource::Url(url) => {
+            let response = RetryingClient::new(&config.retry, deadline)
+                .get(url.clone())
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(Error::HttpStatus {
+                    url: response.url().clone(),
+                    status: response.status(),
+                });
+            }
+
+            response.text().await.map_err(Error::Decode)
+        }
+        IcalSource::File(path) => {
+            tokio::fs::read_to_string(path)
+                .await
+                .map_err(|err| Error::ReadFile {
+                    path: path.clone(),
+                    err,
+                })
+        }
+    }
+}
+
+/// Every pickup from `today` until the horizon, ordered by date.
+fn containers(calendar: &Calendar, config: &IcalConfig, today: NaiveDate) -> Vec<Container> {
+    let until = today + Days::new(config.horizon_days.into());
+    let stand_name = calendar
+        .name
+        .clone()
+        .unwrap_or_else(|| config.source.to_string());
+    info!("Received information for calendar: {}", stand_name);
+
+    let mut containers = Vec::new();
+    for (event, dates) in calendar.occurrences(today, until) {
+        let names = fractions(&event.summary, &config.fractions);
+        if names.is_empty() {
+            info!("Ignoring event '{}', no fraction matches", event.summary);
+            continue;
+        }
+
+        for date in dates {
+            for name in &names {
+                containers.push(Container::new(
+                    name.clone(),
+                    date,
+                    STAND_ID.to_string(),
+                    stand_name.clone(),
+                ));
+            }
+        }
+    }
+
+    containers.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.name.cmp(&b.name)));
+    containers
+}
+
+/// The fractions picked up by an event, one event can cover several fractions.
+fn fractions(summary: &str, rules: &[FractionRule]) -> Vec<String> {
+    if rules.is_empty() {
+        return [summary]
+            .into_iter()
+            .filter(|x| !x.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+
+    let mut names = Vec::new();
+    for rule in rules {
+        if let Some(captures) = rule.pattern.captures(summary) {
+            let mut name = String::new();
+            captures.expand(&rule.name, &mut name);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::DEFAULT_TIMEZONE, ical::settings::RetryConfig};
+    use fluent_asserter::*;
+    use regex::Regex;
+    use url::Url;
+
+    const CALENDAR: &str = "src/ical/remote_responses/collection_calendar.ics";
+
+    fn config(source: IcalSource) -> IcalConfig {
+        IcalConfig {
+            source,
+            fractions: vec![
+                rule("(?i)restaffald", "Restaffald"),
+                rule("(?i)madaffald", "Madaffald"),
+                rule("(?i)papir", "Papir og pap"),
+                rule("(?i)^(glas|storskrald)", "$1"),
+            ],
+            horizon_days: 60,
+            retry: RetryConfig::default(),
+        }
+    }
+
+    fn deadline() -> Instant {
+        Instant::now() + std::time::Duration::from_secs(10)
+    }
+
+    fn rule(pattern: &str, name: &str) -> FractionRule {
+        FractionRule {
+            pattern: Regex::new(pattern).unwrap(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn can_expand_calendar_within_horizon() {
+        let config = config(IcalSource::File(CALENDAR.into()));
+        let calendar = Calendar::parse(
+            &std::fs::read_to_string(CALENDAR).unwrap(),
+            DEFAULT_TIMEZONE,
+        )
+        .unwrap();
+
+        let actual = containers(&calendar, &config, "2026-10-18".parse().unwrap());
+
+        insta::assert_debug_snapshot!(actual);
+    }
+
+    #[test]
+    fn uses_summary_without_rules() {
+        let config = IcalConfig {
+            fractions: Vec::new(),
+            ..config(IcalSource::File(CALENDAR.into()))
+        };
+        let calendar = Calendar::parse(
+            &std::fs::read_to_string(CALENDAR).unwrap(),
+            DEFAULT_TIMEZONE,
+        )
+        .unwrap();
+
+        let actual = containers(&calendar, &config, "2026-10-18".parse().unwrap());
+
+        let mut names: Vec<_> = actual.iter().map(|x| x.name.as_str()).collect();
+        names.dedup();
+        assert_that!(names.contains(&"Juleferie, ingen tømning")).is_true();
+        assert_that!(names.contains(&"Restaffald og madaffald")).is_true();
+    }
+
+    #[tokio::test]
+    async fn can_read_calendar_from_url() {
+        let mut remote = mockito::Server::new_async().await;
+        let url = Url::parse(&format!("{}/affald.ics", remote.url())).unwrap();
+        let remote = remote
+            .mock("GET", "/affald.ics")
+            .with_status(200)
+            .with_header("content-type", "text/calendar")
+            .with_body(weekly_calendar())
+            .create_async()
+            .await;
+
+        let actual =
+            get_containers(&config(IcalSource::Url(url)), DEFAULT_TIMEZONE, deadline()).await;
+
+        remote.assert_async().await;
+        assert_that!(actual.is_ok()).is_true();
+        let actual = actual.unwrap();
+        assert_that!(actual.len()).is_greater_than_or_equal_to(8);
+        assert_that!(actual.iter().all(|x| x.name == "Restaffald")).is_true();
+    }
+
+    #[tokio::test]
+    async fn can_read_calendar_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("affald.ics");
+        std::fs::write(&path, weekly_calendar()).unwrap();
+
+        let actual = get_containers(
+            &config(IcalSource::File(path)),
+            DEFAULT_TIMEZONE,
+            deadline(),
+        )
+        .await;
+
+        assert_that!(actual.is_ok()).is_true();
+        assert_that!(actual.unwrap()[0].stand_name.as_str()).is_equal_to("Kongevejen 100");
+    }
+
+    #[tokio::test]
+    async fn can_handle_invalid_calendar() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("affald.ics");
+        std::fs::write(&path, "<html>Not found</html>").unwrap();
+
+        let actual = get_containers(
+            &config(IcalSource::File(path)),
+            DEFAULT_TIMEZONE,
+            deadline(),
+        )
+        .await;
+
+        assert!(matches!(actual, Err(Error::InvalidCalendar(_))));
+    }
+
+    /// A calendar that never runs out, so it always has pickups within the horizon.
+    fn weekly_calendar() -> &'static str {
+        "BEGIN:VCALENDAR\r\n\
+         VERSION:2.0\r\n\
+         X-WR-CALNAME:Kongevejen 100\r\n\
+         BEGIN:VEVENT\r\n\
+         UID:weekly@example.dk\r\n\
+         DTSTART;VALUE=DATE:20240101\r\n\
+         RRULE:FREQ=WEEKLY\r\n\
+         SUMMARY:Restaffald\r\n\
+         END:VEVENT\r\n\
+         END:VCALENDAR\r\n"
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use url::Url;
+
+pub use crate::settings::RetryConfig;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct IcalConfig {
+    /// Where the calendar is read from, a http(s) URL or the path of a local .ics file.
+    pub source: IcalSource,
+    /// Maps event summaries to fraction names, without rules the summary is used as is.
+    #[serde(default)]
+    pub fractions: Vec<FractionRule>,
+    /// How many days ahead recurring events are expanded.
+    #[serde(default = "default_horizon_days")]
+    pub horizon_days: u32,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+fn default_horizon_days() -> u32 {
+    90
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(from = "String")]
+pub enum IcalSource {
+    Url(Url),
+    File(PathBuf),
+}
+
+impl From<String> for IcalSource {
+    fn from(value: String) -> Self {
+        match Url::parse(&value) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => IcalSource::Url(url),
+            Ok(url) if url.scheme() == "file" => url
+                .to_file_path()
+                .map(IcalSource::File)
+                .unwrap_or_else(|_| IcalSource::File(value.into())),
+            _ => IcalSource::File(value.into()),
+        }
+    }
+}
+
+impl Display for IcalSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IcalSource::Url(url) => write!(f, "{}", url),
+            IcalSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Every event whose summary matches `pattern` is a pickup of the fraction `name`.
+/// The name may refer to capture groups of the pattern, e.g. `$1`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FractionRule {
+    #[serde(deserialize_with = "regex")]
+    pub pattern: Regex,
+    pub name: String,
+}
+
+fn regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
@@ -1,66 +1,216 @@
-use mitaffald::{get_containers, Container};
-use rumqttc::AsyncClient;
-use settings::Settings;
-use std::collections::{hash_map::Entry, HashMap};
+use cache::PlanCache;
+use chrono::NaiveDate;
+use error::Error;
+use fractions::FractionMapping;
+use homeassistant::{HADevice, InitializedState, MqttClient, Topics};
+use provider::{Container, FractionSchedule, Provider, WasteProvider};
+use settings::{AddressConfig, Settings};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
 
+pub mod cache;
+pub mod changes;
+pub mod clock;
+pub mod error;
+pub mod fractions;
 pub mod homeassistant;
+pub mod ical;
 pub mod mitaffald;
+pub mod provider;
+pub mod recurrence;
+mod retry;
 pub mod settings;
 
-pub async fn sync_data(settings: Settings) -> Result<(), String> {
-    let (mut client, mut connection) = AsyncClient::new(settings.mqtt.into(), 200);
-    let device = homeassistant::HADevice::default();
-
-    let mut device = device.initialize(&mut client).await?;
-
-    let containers_to_report = get_containers(settings.affaldvarme)
-        .await?
-        .into_iter()
-        .fold(
-            HashMap::<String, Container>::new(),
-            |mut accumulator, item| {
-                match accumulator.entry(item.name.clone()) {
-                    Entry::Occupied(mut existing) => {
-                        if existing.get().date > item.date {
-                            existing.insert(item);
-                        }
-                    }
-                    Entry::Vacant(v) => {
-                        v.insert(item);
-                    }
-                }
+/// Where the collection plans are taken from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    /// Fetch from the providers, falling back to the cached plan when they fail.
+    Provider,
+    /// Only the cached plans, used to move on to the next pickups between two fetches.
+    Cache,
+}
 
-                accumulator
-            },
-        )
-        .into_values();
+/// Fetches the collection plans of all addresses and publishes them.
+pub async fn sync_data(settings: Settings) -> Result<(), Error> {
+    let today = clock::today(settings.timezone);
+    synchronize(settings, Source::Provider, today).await
+}
 
-    let has_errors = {
-        let mut has_errors = false;
-        for container in containers_to_report {
-            let report_result = device.report(container, &mut client).await;
+/// Publishes the cached collection plans again, so fractions picked up yesterday move on to their
+/// next pickup without contacting the providers.
+pub async fn roll_over(settings: Settings) -> Result<(), Error> {
+    let today = clock::today(settings.timezone);
+    synchronize(settings, Source::Cache, today).await
+}
+
+/// Publishes the pickups of all addresses from `today` onwards.
+pub async fn synchronize(
+    settings: Settings,
+    source: Source,
+    today: NaiveDate,
+) -> Result<(), Error> {
+    let run_id = format!("{:016x}", rand::random::<u64>());
+    info!("Starting synchronization run {}", run_id);
+    // one budget for all addresses, a slow provider cannot hold up the run indefinitely
+    let deadline = Instant::now() + Duration::from_secs(settings.sync_deadline_seconds);
 
-            has_errors = has_errors || report_result.is_err();
+    let (client, connection) = MqttClient::new(settings.mqtt.clone().try_into()?, 200);
+    // sending while publishing, the client waits once it buffered 200 messages
+    let connection = tokio::spawn(connection.run());
+    // a state outlives one missed synchronization before the broker drops it
+    let mut client = client
+        .with_run_id(run_id)
+        .with_state_expiry(Duration::from_secs(
+            settings.update_interval_minutes * 60 * 2,
+        ));
+
+    let mut errors = Vec::new();
+    for config in &settings.affaldvarme {
+        let id = config.id.clone();
+        if let Err(x) = sync_address(config, &settings, source, today, deadline, &mut client).await
+        {
+            error!("Failed to synchronize address '{}', error: {}", id, x);
+            errors.push(x);
         }
-        has_errors
-    };
+    }
+
+    let closed = client.close().await;
+    // a failed connection explains failed publishes and closing better than they do themselves
+    let rejected = connection.await.expect("MQTT connection panicked")?;
+    closed?;
 
-    //calling disconnect() causes an error in the connection iterator
-    if let Err(x) = client.disconnect().await {
-        return Err(x.to_string());
+    for x in rejected {
+        error!("{}", x);
+        errors.push(x);
     }
 
-    //iterate the connection untill we hit the error generated by disconnect()
-    loop {
-        let notification = connection.poll().await;
-        if notification.is_err() {
-            break;
+    Error::collect(errors).map_or(Ok(()), Err)
+}
+
+async fn sync_address(
+    config: &AddressConfig,
+    settings: &Settings,
+    source: Source,
+    today: NaiveDate,
+    deadline: Instant,
+    client: &mut MqttClient,
+) -> Result<(), Error> {
+    let cache = PlanCache::new(&settings.state.dir, &config.id);
+    let max_stale = chrono::Duration::hours(settings.state.max_stale_hours as i64);
+
+    // a plan that could not be cached is still published, the failure is returned afterwards
+    let mut stored = Ok(());
+
+    // the plan of the previous fetch, only known when a new one was fetched to compare it with
+    let (mut device, containers, previous) = match source {
+        Source::Provider => {
+            let mut device = initialize_device(config, settings, client).await?;
+            let provider = Provider::new(
+                &config.provider,
+                &settings.state,
+                settings.timezone,
+                deadline,
+            );
+            let (containers, previous) = match provider.get_containers().await {
+                Ok(containers) => {
+                    let previous = cache.load().map(|x| x.containers);
+                    // the roll over at midnight only reads the cache, it would silently keep
+                    // showing today's pickups
+                    stored = cache.store(&containers).map_err(|err| Error::WriteFile {
+                        path: cache.path().to_owned(),
+                        err,
+                    });
+                    (containers, previous)
+                }
+                Err(err) => match cache.load() {
+                    Some(cached) if cached.age() <= max_stale => {
+                        warn!(
+                            "Failed to fetch the collection plan, serving the one fetched at {}, error: {}",
+                            cached.fetched_at, err
+                        );
+                        device.mark_stale(cached.fetched_at);
+                        (cached.containers, None)
+                    }
+                    Some(_) => {
+                        device.mark_unavailable(client).await?;
+                        return Err(err);
+                    }
+                    None => return Err(err),
+                },
+            };
+
+            (device, containers, previous)
         }
+        Source::Cache => match cache.load() {
+            // nothing to move on from, or already reported as unavailable by the last fetch
+            None => return Ok(()),
+            Some(cached) if cached.age() > max_stale => return Ok(()),
+            Some(cached) => {
+                let mut device = initialize_device(config, settings, client).await?;
+                // a plan that survived the last fetch means that fetch failed
+                if cached.age() > chrono::Duration::minutes(settings.update_interval_minutes as i64)
+                {
+                    device.mark_stale(cached.fetched_at);
+                }
+
+                (device, cached.containers, None)
+            }
+        },
+    };
+
+    let mapping = FractionMapping::new(&settings.fractions);
+    let containers = upcoming(mapping.apply(containers), today);
+    if containers.is_empty() {
+        warn!("No upcoming pickups for address '{}'", config.id);
+    }
+
+    let changes = previous
+        .map(|previous| changes::detect(&mapping.apply(previous), &containers, today))
+        .unwrap_or_default();
+    for change in &changes {
+        info!("Schedule of address '{}' changed: {:?}", config.id, change);
     }
 
-    if has_errors {
-        Err("Failed to report all containers".into())
-    } else {
-        Ok(())
+    let mut schedules = FractionSchedule::group(containers);
+    mapping.mark_renamed(&mut schedules);
+    mapping.assign_icons(&mut schedules);
+
+    let reported = device.report(&schedules, today, client).await;
+    device.report_changes(&changes, client).await?;
+
+    reported.and(stored)
+}
+
+async fn initialize_device(
+    config: &AddressConfig,
+    settings: &Settings,
+    client: &mut MqttClient,
+) -> Result<HADevice<InitializedState>, Error> {
+    HADevice::new(
+        config.id.clone(),
+        config.name.clone(),
+        Topics::new(&settings.mqtt),
+    )
+    .with_upcoming_days(config.upcoming_days)
+    .with_predictions(config.predict.then_some(config.predict_days))
+    .with_language(settings.language)
+    .initialize(client)
+    .await
+}
+
+/// Drops the pickups before `today`, the API keeps listing them for a while.
+fn upcoming(containers: Vec<Container>, today: NaiveDate) -> Vec<Container> {
+    let total = containers.len();
+    let containers: Vec<Container> = containers.into_iter().filter(|x| x.date >= today).collect();
+
+    if containers.len() < total {
+        info!(
+            "Ignoring {} pickup(s) before {}",
+            total - containers.len(),
+            today
+        );
     }
+
+    containers
 }
@@ -1,10 +1,34 @@
-use ha_mitaffald::settings::Settings;
-use ha_mitaffald::sync_data;
+use ha_mitaffald::error::Error;
+use ha_mitaffald::mitaffald::{
+    lookup,
+    settings::{DEFAULT_ADDRESS_LOOKUP_URL, DEFAULT_BASE_URL},
+};
+use ha_mitaffald::settings::{ProviderConfig, Settings, DEFAULT_SYNC_DEADLINE_SECONDS};
+use ha_mitaffald::{clock, roll_over, sync_data};
 use tracing::{error, info, Level};
 use tracing_subscriber::FmtSubscriber;
+use url::Url;
+
+const USAGE: &str = "Usage:
+  ha-mitaffald                    synchronize the configured addresses periodically
+  ha-mitaffald lookup <address>   search for an address and print its Kredsløb id, e.g.
+                                  ha-mitaffald lookup \"Kongevejen 100 8000\"";
 
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        None => run().await,
+        Some("lookup") if args.len() > 1 => run_lookup(&args[1..].join(" ")).await,
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+async fn run() {
     let subscriber = FmtSubscriber::builder()
         .with_max_level(Level::INFO)
         .finish();
@@ -16,11 +40,16 @@ async fn main() {
         let settings = Settings::new().expect("Failed to read settings");
         let update_interval =
             tokio::time::Duration::from_secs(settings.update_interval_minutes * 60);
+        let timezone = settings.timezone;
 
         let report = sync_data(settings).await;
 
         match report {
             Ok(_) => info!("Data synchronization completed"),
+            Err(x @ (Error::MqttConnection(_) | Error::MqttV5Connection(_))) => error!(
+                "Data synchronization failed, the MQTT broker could not be reached, error: {}",
+                x
+            ),
             Err(x) => error!(
                 "Data synchronization failed (some entities may have been updated), error: {}",
                 x
@@ -34,6 +63,83 @@ async fn main() {
             (chrono::Local::now() + update_interval).format("%Y-%m-%d %H:%M:%S")
         );
 
-        tokio::time::sleep(update_interval).await;
+        wait_for_next_sync(update_interval, timezone).await;
+    }
+}
+
+/// Sleeps until the next synchronization is due, rolling over to the next pickups at every
+/// local midnight on the way so the sensors never show a pickup that already happened.
+async fn wait_for_next_sync(update_interval: tokio::time::Duration, timezone: chrono_tz::Tz) {
+    let next_sync = tokio::time::Instant::now() + update_interval;
+
+    loop {
+        let now = chrono::Utc::now();
+        let until_midnight = (clock::next_midnight(now, timezone) - now)
+            .to_std()
+            .unwrap_or_default();
+        let midnight = tokio::time::Instant::now() + until_midnight;
+
+        if midnight >= next_sync {
+            tokio::time::sleep_until(next_sync).await;
+            return;
+        }
+
+        tokio::time::sleep_until(midnight).await;
+
+        info!("Rolling over to the next pickups");
+        let settings = Settings::new().expect("Failed to read settings");
+        if let Err(x) = roll_over(settings).await {
+            error!("Rolling over failed, error: {}", x);
+        }
+    }
+}
+
+/// Prints every matching address as a config section, logs go to stderr to keep stdout paste-ready.
+async fn run_lookup(query: &str) {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::WARN)
+        .with_writer(std::io::stderr)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let address_lookup_url = Url::parse(DEFAULT_ADDRESS_LOOKUP_URL).expect("Invalid DAWA url");
+    let base_url = Url::parse(DEFAULT_BASE_URL).expect("Invalid Kredsløb url");
+
+    // retried like the first configured Kredsløb address, when there is a config to read
+    let settings = Settings::new().ok();
+    let retry = settings
+        .as_ref()
+        .and_then(|x| {
+            x.affaldvarme
+                .iter()
+                .find_map(|address| match &address.provider {
+                    ProviderConfig::Kredslob(config) => Some(config.retry.clone()),
+                    ProviderConfig::Ical(_) => None,
+                })
+        })
+        .unwrap_or_default();
+    let deadline = tokio::time::Instant::now()
+        + tokio::time::Duration::from_secs(
+            settings.map_or(DEFAULT_SYNC_DEADLINE_SECONDS, |x| x.sync_deadline_seconds),
+        );
+
+    match lookup(query, &address_lookup_url, &base_url, &retry, deadline).await {
+        Ok(results) if results.is_empty() => {
+            eprintln!("No address matched '{}'", query);
+            std::process::exit(1);
+        }
+        Ok(results) => {
+            for (i, result) in results.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                print!("{}", result);
+            }
+        }
+        Err(x) => {
+            eprintln!("Lookup failed, error: {}", x);
+            std::process::exit(1);
+        }
     }
 }
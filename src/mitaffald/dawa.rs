@@ -0,0 +1,270 @@
+use serde::Deserialize;
+use url::Url;
+
+use super::settings::{AddressId, TraditionalAddress};
+use crate::{error::Error, retry::RetryingClient};
+
+/// Addresses requested at a time, large buildings take several pages.
+const PAGE_SIZE: usize = 100;
+
+/// An address returned by DAWA (Danmarks Adressers Web API).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AddressCandidate {
+    pub kvhx: String,
+    #[serde(rename = "adressebetegnelse")]
+    pub text: String,
+    #[serde(rename = "etage")]
+    pub floor: Option<String>,
+    #[serde(rename = "dør")]
+    pub door: Option<String>,
+}
+
+/// Resolves the address to exactly one id, failing with the ranked candidates when it is ambiguous.
+pub(super) async fn lookup_address(
+    address: &TraditionalAddress,
+    client: &RetryingClient,
+) -> Result<AddressId, Error> {
+    let query = describe(address);
+    let mut candidates = search(
+        &address.address_lookup_url,
+        Query::Structured(address),
+        client,
+    )
+    .await?;
+
+    match candidates.len() {
+        0 => Err(Error::AddressNotFound { query }),
+        1 => Ok(AddressId {
+            id: candidates.remove(0).kvhx,
+        }),
+        _ => {
+            rank(&mut candidates);
+            Err(Error::AddressAmbiguous { query, candidates })
+        }
+    }
+}
+
+pub(super) enum Query<'a> {
+    /// Matches the individual address fields exactly.
+    Structured(&'a TraditionalAddress),
+    /// Free text search, e.g. "Kongevejen 100 8000".
+    FreeText(&'a str),
+}
+
+/// All addresses matching the query, in the order DAWA returned them, from every page.
+pub(super) async fn search(
+    address_lookup_url: &Url,
+    query: Query<'_>,
+    client: &RetryingClient,
+) -> Result<Vec<AddressCandidate>, Error> {
+    let mut url_builder = address_lookup_url.clone();
+    url_builder.set_path("adresser");
+
+    {
+        let mut query_pairs = url_builder.query_pairs_mut();
+        match query {
+            Query::Structured(address) => {
+                query_pairs
+                    .append_pair("vejnavn", address.street_name.trim())
+                    .append_pair("husnr", address.street_no.trim())
+                    .append_pair("postnr", address.postal_code.trim());
+
+                if let Some(floor) = &address.floor {
+                    query_pairs.append_pair("etage", floor.trim());
+                }
+                if let Some(door) = &address.door {
+                    query_pairs.append_pair("dør", door.trim());
+                }
+            }
+            Query::FreeText(text) => {
+                query_pairs.append_pair("q", text.trim());
+            }
+        }
+
+        query_pairs.append_pair("per_side", &PAGE_SIZE.to_string());
+    }
+
+    let mut candidates = Vec::new();
+    for page in 1.. {
+        let mut url = url_builder.clone();
+        url.query_pairs_mut().append_pair("side", &page.to_string());
+
+        let response = client.get(url).await?;
+
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus {
+                url: response.url().clone(),
+                status: response.status(),
+            });
+        }
+
+        let found = response
+            .json::<Vec<AddressCandidate>>()
+            .await
+            .map_err(Error::Decode)?;
+        let last_page = found.len() < PAGE_SIZE;
+        candidates.extend(found);
+
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Orders candidates the way they appear in a building: by floor (basement, ground floor, 1st...),
+/// then door (left, middle, right, numbered...), then id.
+pub fn rank(candidates: &mut [AddressCandidate]) {
+    candidates.sort_by(|a, b| {
+        floor_rank(a.floor.as_deref())
+            .cmp(&floor_rank(b.floor.as_deref()))
+            .then_with(|| door_rank(a.door.as_deref()).cmp(&door_rank(b.door.as_deref())))
+            .then_with(|| a.kvhx.cmp(&b.kvhx))
+    });
+}
+
+fn describe(address: &TraditionalAddress) -> String {
+    let unit = [address.floor.as_deref(), address.door.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if unit.is_empty() {
+        format!(
+            "{} {}, {} {}",
+            address.street_name, address.street_no, address.postal_code, address.city
+        )
+    } else {
+        format!(
+            "{} {}, {}, {} {}",
+            address.street_name, address.street_no, unit, address.postal_code, address.city
+        )
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Rank<'a> {
+    Known(i64),
+    Other(&'a str),
+}
+
+fn floor_rank(floor: Option<&str>) -> Rank<'_> {
+    match floor.map(str::to_lowercase).as_deref() {
+        None => Rank::Known(-2),
+        Some("kl") => Rank::Known(-1),
+        Some("st") => Rank::Known(0),
+        Some(x) => x
+            .parse()
+            .map(Rank::Known)
+            .unwrap_or_else(|_| Rank::Other(floor.unwrap_or_default())),
+    }
+}
+
+fn door_rank(door: Option<&str>) -> Rank<'_> {
+    match door.map(str::to_lowercase).as_deref() {
+        None => Rank::Known(0),
+        Some("tv") => Rank::Known(1),
+        Some("mf") => Rank::Known(2),
+        Some("th") => Rank::Known(3),
+        Some(x) => x
+            .parse::<i64>()
+            .map(|x| Rank::Known(x + 3))
+            .unwrap_or_else(|_| Rank::Other(door.unwrap_or_default())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::RetryConfig;
+    use mockito::Matcher;
+    use tokio::time::Instant;
+
+    fn candidate(kvhx: &str, floor: Option<&str>, door: Option<&str>) -> AddressCandidate {
+        AddressCandidate {
+            kvhx: kvhx.to_string(),
+            text: kvhx.to_string(),
+            floor: floor.map(str::to_string),
+            door: door.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn ranks_candidates_by_floor_then_door() {
+        let mut candidates = vec![
+            candidate("07514448_100_2_th", Some("2"), Some("th")),
+            candidate("07514448_100_10_tv", Some("10"), Some("tv")),
+            candidate("07514448_100_st_tv", Some("st"), Some("tv")),
+            candidate("07514448_100_2_tv", Some("2"), Some("tv")),
+            candidate("07514448_100_kl_1", Some("kl"), Some("1")),
+            candidate("07514448_100", None, None),
+            candidate("07514448_100_2_mf", Some("2"), Some("mf")),
+        ];
+
+        rank(&mut candidates);
+
+        let actual: Vec<_> = candidates.iter().map(|x| x.kvhx.as_str()).collect();
+        assert_eq!(
+            actual,
+            vec![
+                "07514448_100",
+                "07514448_100_kl_1",
+                "07514448_100_st_tv",
+                "07514448_100_2_tv",
+                "07514448_100_2_mf",
+                "07514448_100_2_th",
+                "07514448_100_10_tv",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn searches_every_page() {
+        let mut remote = mockito::Server::new_async().await;
+        let remote_url = Url::parse(remote.url().as_str()).unwrap();
+        let page = |floors: std::ops::Range<usize>| {
+            serde_json::Value::from_iter(floors.map(|floor| {
+                serde_json::json!({
+                    "kvhx": format!("07514448_100_{}_tv", floor),
+                    "adressebetegnelse": format!("Kongevejen 100, {}. tv, 8000 Aarhus C", floor),
+                    "etage": floor.to_string(),
+                    "dør": "tv",
+                })
+            }))
+            .to_string()
+        };
+
+        let first_page = remote
+            .mock("GET", "/adresser")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("q".into(), "Kongevejen 100 8000".into()),
+                Matcher::UrlEncoded("side".into(), "1".into()),
+            ]))
+            .with_status(200)
+            .with_body(page(0..PAGE_SIZE))
+            .create_async()
+            .await;
+        let second_page = remote
+            .mock("GET", "/adresser")
+            .match_query(Matcher::UrlEncoded("side".into(), "2".into()))
+            .with_status(200)
+            .with_body(page(PAGE_SIZE..PAGE_SIZE + 1))
+            .create_async()
+            .await;
+
+        let client = RetryingClient::new(
+            &RetryConfig::default(),
+            Instant::now() + std::time::Duration::from_secs(10),
+        );
+        let actual = search(&remote_url, Query::FreeText("Kongevejen 100 8000"), &client)
+            .await
+            .unwrap();
+
+        first_page.assert_async().await;
+        second_page.assert_async().await;
+        assert_eq!(actual.len(), PAGE_SIZE + 1);
+        assert_eq!(actual[PAGE_SIZE].kvhx, "07514448_100_100_tv");
+    }
+}
@@ -1,35 +1,84 @@
+pub mod dawa;
 pub mod settings;
 
-use chrono::{DateTime, Utc};
+use std::fmt::{self, Display, Formatter};
+
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
 use serde::Deserialize;
 use settings::{Address, AffaldVarmeConfig};
-use tracing::info;
+use tokio::time::Instant;
+use tracing::{info, warn};
 use url::Url;
 
-use self::settings::{AddressId, TraditionalAddress};
-
-pub async fn get_containers(config: AffaldVarmeConfig) -> Result<Vec<Container>, String> {
-    let response = fetch_collection_plan(config).await?;
+use self::dawa::AddressCandidate;
+use self::settings::{AddressId, RetryConfig, TraditionalAddress};
+use crate::{
+    cache::AddressCache,
+    error::Error,
+    provider::{Container, WasteProvider},
+    retry::RetryingClient,
+};
+
+/// Kredsløb (formerly AffaldVarme Aarhus) as a [`WasteProvider`].
+pub struct Kredslob {
+    config: AffaldVarmeConfig,
+    address_cache: AddressCache,
+    timezone: Tz,
+    deadline: Instant,
+}
 
-    if !response.status().is_success() {
-        return Err(format!("Unexpected status code: {:?}", response.status()));
+impl Kredslob {
+    pub fn new(
+        config: AffaldVarmeConfig,
+        address_cache: AddressCache,
+        timezone: Tz,
+        deadline: Instant,
+    ) -> Self {
+        Self {
+            config,
+            address_cache,
+            timezone,
+            deadline,
+        }
     }
+}
 
-    response
-        .json::<Response>()
+impl WasteProvider for Kredslob {
+    async fn get_containers(&self) -> Result<Vec<Container>, Error> {
+        get_containers(
+            &self.config,
+            &self.address_cache,
+            self.timezone,
+            self.deadline,
+        )
         .await
-        .map_err(|err| format!("Error reading response content: {:?}", err))
-        .and_then(|response| {
-            response
-                .0
-                .into_iter()
-                .next()
-                .ok_or_else(|| "No data found".to_string())
-                .map(|response| {
-                    info!("Received information for stand: {}", response.stand_name);
-                    response.into()
-                })
+    }
+}
+
+/// The collection plan of the configured address, fetched before `deadline`.
+pub async fn get_containers(
+    config: &AffaldVarmeConfig,
+    address_cache: &AddressCache,
+    timezone: Tz,
+    deadline: Instant,
+) -> Result<Vec<Container>, Error> {
+    let client = RetryingClient::new(&config.retry, deadline);
+    let remote_url = build_remote_url(config, &client, address_cache).await?;
+    let response = fetch_collection_plan(remote_url, &client).await?;
+
+    if response.0.is_empty() {
+        return Err(Error::NoData);
+    }
+
+    Ok(response
+        .0
+        .into_iter()
+        .flat_map(|response| {
+            info!("Received information for stand: {}", response.stand_name);
+            response.into_containers(timezone)
         })
+        .collect())
 }
 
 #[derive(Deserialize)]
@@ -37,7 +86,6 @@ struct Response(Vec<StandCollectionPlan>);
 
 #[derive(Deserialize)]
 struct StandCollectionPlan {
-    #[allow(dead_code)]
     #[serde(rename = "standId")]
     stand_id: String,
 
@@ -50,88 +98,144 @@ struct StandCollectionPlan {
 
 #[derive(Deserialize)]
 struct PlannedLoad {
-    date: DateTime<Utc>,
+    /// Midnight of the pickup date, the offset is not necessarily the local one.
+    date: DateTime<FixedOffset>,
     fractions: Vec<String>,
 }
 
-async fn fetch_collection_plan(config: AffaldVarmeConfig) -> Result<reqwest::Response, String> {
-    let remote_url = build_remote_url(config).await?;
+/// An address found by [`lookup`], with the Kredsløb stands serving it.
+pub struct LookupResult {
+    pub candidate: AddressCandidate,
+    pub stand_names: Vec<String>,
+}
 
-    reqwest::get(remote_url)
-        .await
-        .map_err(|err| format!("Error connecting: {:?}", err))
+/// Renders the result as a commented `[affaldvarme.address]` section, ready to paste into the config.
+impl Display for LookupResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# {}", self.candidate.text)?;
+        if self.stand_names.is_empty() {
+            writeln!(f, "# No Kredsløb collection plan found for this address")?;
+        }
+        for stand_name in &self.stand_names {
+            writeln!(f, "# Kredsløb stand: {}", stand_name)?;
+        }
+        writeln!(f, "[affaldvarme.address]")?;
+        writeln!(f, "id = \"{}\"", self.candidate.kvhx)
+    }
 }
 
-async fn build_remote_url(config: AffaldVarmeConfig) -> Result<Url, String> {
-    let mut url_builder = config.base_url.clone();
+/// Searches addresses by free text and finds the Kredsløb stands of every match, all before
+/// `deadline`.
+pub async fn lookup(
+    query: &str,
+    address_lookup_url: &Url,
+    base_url: &Url,
+    retry: &RetryConfig,
+    deadline: Instant,
+) -> Result<Vec<LookupResult>, Error> {
+    let client = RetryingClient::new(retry, deadline);
+    let mut candidates =
+        dawa::search(address_lookup_url, dawa::Query::FreeText(query), &client).await?;
+    dawa::rank(&mut candidates);
+
+    let mut results = Vec::with_capacity(candidates.len());
+    for candidate in candidates {
+        let stand_names =
+            match fetch_collection_plan(calendar_url(base_url, &candidate.kvhx), &client).await {
+                Ok(response) => response.0.into_iter().map(|x| x.stand_name).collect(),
+                // the remaining candidates would fail the same way, without telling why
+                Err(Error::DeadlineExceeded) => return Err(Error::DeadlineExceeded),
+                Err(err) => {
+                    warn!(
+                        "No collection plan found for {}, error: {}",
+                        candidate.kvhx, err
+                    );
+                    Vec::new()
+                }
+            };
+
+        results.push(LookupResult {
+            candidate,
+            stand_names,
+        });
+    }
+
+    Ok(results)
+}
 
-    let address_id = match config.address {
+async fn fetch_collection_plan(
+    remote_url: Url,
+    client: &RetryingClient,
+) -> Result<Response, Error> {
+    let response = client.get(remote_url).await?;
+
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus {
+            url: response.url().clone(),
+            status: response.status(),
+        });
+    }
+
+    response.json::<Response>().await.map_err(Error::Decode)
+}
+
+async fn build_remote_url(
+    config: &AffaldVarmeConfig,
+    client: &RetryingClient,
+    address_cache: &AddressCache,
+) -> Result<Url, Error> {
+    let address_id = match &config.address {
         Address::Id(x) => x.id.clone(),
-        Address::FullySpecified(x) => lookup_address(x).await?.id.clone(),
+        Address::FullySpecified(x) => resolve_address(x, client, address_cache).await?.id,
     };
 
-    url_builder.set_path(format!("api/calendar/address/{}", dbg!(address_id)).as_str());
-
-    Ok(url_builder)
+    Ok(calendar_url(&config.base_url, &address_id))
 }
 
-async fn lookup_address(address: TraditionalAddress) -> Result<AddressId, String> {
-    let mut url_builder = address.address_lookup_url.clone();
-    url_builder.set_path("adresser");
+fn calendar_url(base_url: &Url, address_id: &str) -> Url {
+    let mut url_builder = base_url.clone();
+    url_builder.set_path(format!("api/calendar/address/{}", address_id).as_str());
 
     url_builder
-        .query_pairs_mut()
-        .append_pair(
-            "q",
-            format!(
-                "{} {} {} {}",
-                address.street_name, address.street_no, address.postal_code, address.city
-            )
-            .as_str(),
-        )
-        .append_pair("per_side", "2");
-
-    let response = reqwest::get(url_builder)
-        .await
-        .map_err(|e| e.to_string())?
-        .json::<serde_json::Value>()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    response
-        .as_array()
-        .and_then(|x| if x.len() == 1 { x.first() } else { None })
-        .and_then(|x| {
-            x.get("kvhx")
-                .and_then(|x| x.as_str())
-                .map(|x| AddressId { id: x.to_owned() })
-        })
-        .ok_or_else(|| {
-            "Address not found or multiple addresses matched the search criteria".to_string()
-        })
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Container {
-    pub name: String,
-    pub date: DateTime<Utc>,
-}
+async fn resolve_address(
+    address: &TraditionalAddress,
+    client: &RetryingClient,
+    address_cache: &AddressCache,
+) -> Result<AddressId, Error> {
+    let key = address.cache_key();
+
+    if !address.force_lookup {
+        if let Some(id) = address_cache.get(&key) {
+            return Ok(AddressId { id });
+        }
+    }
 
-impl Container {
-    fn new(name: String, date: DateTime<Utc>) -> Self {
-        Self { name, date }
+    let address_id = dawa::lookup_address(address, client).await?;
+
+    if let Err(x) = address_cache.insert(&key, &address_id.id) {
+        warn!("Failed to remember the address id, error: {}", x);
     }
+
+    Ok(address_id)
 }
 
-impl From<StandCollectionPlan> for Vec<Container> {
-    fn from(response: StandCollectionPlan) -> Self {
-        response
-            .planned_loads
+impl StandCollectionPlan {
+    /// Every planned load of every fraction, dated in `timezone`.
+    fn into_containers(self, timezone: Tz) -> Vec<Container> {
+        let stand_id = self.stand_id;
+        let stand_name = self.stand_name;
+
+        self.planned_loads
             .into_iter()
-            .flat_map(move |x| {
+            .flat_map(|x| {
+                let stand_id = &stand_id;
+                let stand_name = &stand_name;
+                let date = x.date.with_timezone(&timezone).date_naive();
                 x.fractions
                     .into_iter()
-                    .map(move |y| Container::new(y, x.date))
+                    .map(move |y| Container::new(y, date, stand_id.clone(), stand_name.clone()))
             })
             .collect()
     }
@@ -140,19 +244,60 @@ impl From<StandCollectionPlan> for Vec<Container> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::mitaffald::settings::{Address, AddressId, TraditionalAddress};
-    use fluent_asserter::{prelude::StrAssertions, *};
+    use crate::clock::DEFAULT_TIMEZONE;
+    use crate::mitaffald::settings::{Address, AddressId, RetryConfig, TraditionalAddress};
+    use chrono::NaiveDate;
+    use fluent_asserter::*;
     use mockito::Matcher;
 
+    #[test]
+    fn dates_loads_in_local_time_across_dst_weekends() {
+        let plan: StandCollectionPlan = serde_json::from_value(serde_json::json!({
+            "standId": "68460",
+            "standName": "Kongevejen 100, 8000 Aarhus C",
+            "plannedLoads": [
+                { "date": "2024-03-30T00:00:00+00:00", "fractions": ["Restaffald"] },
+                { "date": "2024-03-30T23:00:00+00:00", "fractions": ["Restaffald"] },
+                { "date": "2024-03-31T22:00:00+00:00", "fractions": ["Restaffald"] },
+                { "date": "2024-10-26T22:00:00+00:00", "fractions": ["Restaffald"] },
+                { "date": "2024-10-27T23:00:00+00:00", "fractions": ["Restaffald"] },
+                { "date": "2024-10-28T00:00:00+01:00", "fractions": ["Restaffald"] },
+            ]
+        }))
+        .unwrap();
+
+        let actual: Vec<NaiveDate> = plan
+            .into_containers(DEFAULT_TIMEZONE)
+            .into_iter()
+            .map(|x| x.date)
+            .collect();
+
+        let expected: Vec<NaiveDate> = [
+            "2024-03-30",
+            "2024-03-31",
+            "2024-04-01",
+            "2024-10-27",
+            "2024-10-28",
+            "2024-10-28",
+        ]
+        .iter()
+        .map(|x| x.parse().unwrap())
+        .collect();
+        assert_eq!(actual, expected);
+    }
+
     #[tokio::test]
     async fn can_extract_data_using_address_id() {
         let mut remote = mockito::Server::new_async().await;
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
         let address_id = "123".to_string();
         let config = AffaldVarmeConfig {
             address: Address::Id(AddressId {
                 id: address_id.clone(),
             }),
             base_url: Url::parse(remote.url().as_str()).unwrap(),
+            retry: RetryConfig::default(),
         };
 
         let remote = remote
@@ -165,7 +310,34 @@ mod tests {
             .create_async()
             .await;
 
-        let actual = get_containers(config).await;
+        let actual = get_containers(&config, &address_cache, DEFAULT_TIMEZONE, deadline()).await;
+
+        remote.assert_async().await;
+        assert_that!(actual.is_ok()).is_true();
+        insta::assert_debug_snapshot!(actual.unwrap());
+    }
+
+    #[tokio::test]
+    async fn can_extract_data_from_all_stands() {
+        let mut remote = mockito::Server::new_async().await;
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
+        let config = AffaldVarmeConfig {
+            address: Address::Id(AddressId { id: "123".into() }),
+            base_url: Url::parse(remote.url().as_str()).unwrap(),
+            retry: RetryConfig::default(),
+        };
+
+        let remote = remote
+            .mock("GET", "/api/calendar/address/123")
+            .with_status(200)
+            .with_body_from_file(
+                "src/mitaffald/remote_responses/container_information_multiple_stands.json",
+            )
+            .create_async()
+            .await;
+
+        let actual = get_containers(&config, &address_cache, DEFAULT_TIMEZONE, deadline()).await;
 
         remote.assert_async().await;
         assert_that!(actual.is_ok()).is_true();
@@ -175,6 +347,8 @@ mod tests {
     #[tokio::test]
     async fn can_extract_data_using_traditional_address() {
         let mut remote = mockito::Server::new_async().await;
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
         let config = AffaldVarmeConfig {
             address: Address::FullySpecified(TraditionalAddress {
                 street_name: "Kongevejen".to_string(),
@@ -183,15 +357,20 @@ mod tests {
                 city: "Aarhus C".to_string(),
                 address_lookup_url: Url::parse(remote.url().as_str())
                     .expect("Failed to parse address_lookup_url"),
+                floor: None,
+                door: None,
+                force_lookup: false,
             }),
             base_url: Url::parse(remote.url().as_str()).expect("Failed to parse base_url"),
+            retry: RetryConfig::default(),
         };
 
         let address_lookup_mock = remote
             .mock("GET", "/adresser")
             .match_query(Matcher::AllOf(vec![
-                Matcher::UrlEncoded("q".into(), "Kongevejen 100 8000 Aarhus C".into()),
-                Matcher::UrlEncoded("per_side".into(), "2".into()),
+                Matcher::UrlEncoded("vejnavn".into(), "Kongevejen".into()),
+                Matcher::UrlEncoded("husnr".into(), "100".into()),
+                Matcher::UrlEncoded("postnr".into(), "8000".into()),
             ]))
             .with_status(200)
             .with_body_from_file("src/mitaffald/remote_responses/address_lookup.json")
@@ -205,7 +384,7 @@ mod tests {
             .create_async()
             .await;
 
-        let actual = get_containers(config).await;
+        let actual = get_containers(&config, &address_cache, DEFAULT_TIMEZONE, deadline()).await;
 
         address_lookup_mock.assert_async().await;
         container_info_mock.assert_async().await;
@@ -213,37 +392,342 @@ mod tests {
         insta::assert_debug_snapshot!(actual.unwrap());
     }
 
+    #[tokio::test]
+    async fn can_reuse_resolved_address_id() {
+        let mut remote = mockito::Server::new_async().await;
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
+        let remote_url = Url::parse(remote.url().as_str()).unwrap();
+        let config = |street_name: &str| AffaldVarmeConfig {
+            address: Address::FullySpecified(TraditionalAddress {
+                street_name: street_name.to_string(),
+                street_no: "100".to_string(),
+                postal_code: "8000".to_string(),
+                city: "Aarhus C".to_string(),
+                address_lookup_url: remote_url.clone(),
+                floor: None,
+                door: None,
+                force_lookup: false,
+            }),
+            base_url: remote_url.clone(),
+            retry: RetryConfig::default(),
+        };
+
+        let address_lookup_mock = remote
+            .mock("GET", "/adresser")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body_from_file("src/mitaffald/remote_responses/address_lookup.json")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let container_info_mock = remote
+            .mock("GET", "/api/calendar/address/07514448_100_______")
+            .with_status(200)
+            .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+            .expect(2)
+            .create_async()
+            .await;
+
+        let first = get_containers(
+            &config("Kongevejen"),
+            &address_cache,
+            DEFAULT_TIMEZONE,
+            deadline(),
+        )
+        .await;
+        let second = get_containers(
+            &config(" kongevejen "),
+            &address_cache,
+            DEFAULT_TIMEZONE,
+            deadline(),
+        )
+        .await;
+
+        address_lookup_mock.assert_async().await;
+        container_info_mock.assert_async().await;
+        assert_that!(first.is_ok()).is_true();
+        assert_that!(second.is_ok()).is_true();
+    }
+
+    #[tokio::test]
+    async fn can_lookup_addresses_with_their_stands() {
+        let mut remote = mockito::Server::new_async().await;
+        let remote_url = Url::parse(remote.url().as_str()).unwrap();
+
+        let address_lookup_mock = remote
+            .mock("GET", "/adresser")
+            .match_query(Matcher::UrlEncoded(
+                "q".into(),
+                "Kongevejen 100 8000".into(),
+            ))
+            .with_status(200)
+            .with_body_from_file("src/mitaffald/remote_responses/address_lookup.json")
+            .create_async()
+            .await;
+
+        let container_info_mock = remote
+            .mock("GET", "/api/calendar/address/07514448_100_______")
+            .with_status(200)
+            .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+            .create_async()
+            .await;
+
+        let actual = lookup(
+            " Kongevejen 100 8000 ",
+            &remote_url,
+            &remote_url,
+            &RetryConfig::default(),
+            deadline(),
+        )
+        .await;
+
+        address_lookup_mock.assert_async().await;
+        container_info_mock.assert_async().await;
+        let actual = actual.unwrap();
+        assert_eq!(actual.len(), 1);
+        assert_eq!(
+            actual[0].to_string(),
+            "# Kongevejen 100, 8000 Aarhus C\n\
+             # Kredsløb stand: Kongevejen 100, 8000 Aarhus C\n\
+             [affaldvarme.address]\n\
+             id = \"07514448_100_______\"\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn lookup_fails_when_the_deadline_runs_out() {
+        let mut remote = mockito::Server::new_async().await;
+        let remote_url = Url::parse(remote.url().as_str()).unwrap();
+        let address_lookup_mock = remote
+            .mock("GET", "/adresser")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body_from_file("src/mitaffald/remote_responses/address_lookup.json")
+            .create_async()
+            .await;
+
+        // accepts connections but never answers
+        let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent_url = Url::parse(&format!("http://{}", silent.local_addr().unwrap())).unwrap();
+
+        let actual = lookup(
+            "Kongevejen 100 8000",
+            &remote_url,
+            &silent_url,
+            &RetryConfig::default(),
+            Instant::now() + std::time::Duration::from_millis(500),
+        )
+        .await;
+
+        address_lookup_mock.assert_async().await;
+        assert_that!(matches!(actual, Err(Error::DeadlineExceeded))).is_true();
+    }
+
+    #[tokio::test]
+    async fn can_report_ambiguous_address() {
+        let mut remote = mockito::Server::new_async().await;
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
+        let config = AffaldVarmeConfig {
+            address: Address::FullySpecified(TraditionalAddress {
+                street_name: "Kongevejen".to_string(),
+                street_no: "100".to_string(),
+                postal_code: "8000".to_string(),
+                city: "Aarhus C".to_string(),
+                address_lookup_url: Url::parse(remote.url().as_str()).unwrap(),
+                floor: None,
+                door: None,
+                force_lookup: false,
+            }),
+            base_url: Url::parse(remote.url().as_str()).unwrap(),
+            retry: RetryConfig::default(),
+        };
+
+        let address_lookup_mock = remote
+            .mock("GET", "/adresser")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(
+                r#"[
+                    {"kvhx": "07514448_100__1__tv", "adressebetegnelse": "Kongevejen 100, 1. tv, 8000 Aarhus C", "etage": "1", "dør": "tv"},
+                    {"kvhx": "07514448_100__st__th", "adressebetegnelse": "Kongevejen 100, st. th, 8000 Aarhus C", "etage": "st", "dør": "th"},
+                    {"kvhx": "07514448_100__st__tv", "adressebetegnelse": "Kongevejen 100, st. tv, 8000 Aarhus C", "etage": "st", "dør": "tv"}
+                ]"#,
+            )
+            .create_async()
+            .await;
+
+        let actual = get_containers(&config, &address_cache, DEFAULT_TIMEZONE, deadline()).await;
+
+        address_lookup_mock.assert_async().await;
+        match actual {
+            Err(Error::AddressAmbiguous { candidates, .. }) => assert_eq!(
+                candidates
+                    .iter()
+                    .map(|x| x.kvhx.as_str())
+                    .collect::<Vec<_>>(),
+                vec![
+                    "07514448_100__st__tv",
+                    "07514448_100__st__th",
+                    "07514448_100__1__tv"
+                ]
+            ),
+            other => panic!("Expected ambiguous address, got {:?}", other),
+        }
+    }
+
     #[tokio::test]
     async fn can_handle_server_error() {
         let mut remote = mockito::Server::new_async().await;
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
         let config = AffaldVarmeConfig {
             address: Address::Id(AddressId { id: "123".into() }),
             base_url: Url::parse(remote.url().as_str()).unwrap(),
+            retry: fast_retry(),
         };
 
         let remote = remote
             .mock("GET", mockito::Matcher::Regex(".*".to_string()))
             .with_status(500)
+            .expect(fast_retry().max_attempts as usize)
             .create_async()
             .await;
 
-        let actual = get_containers(config).await;
+        let actual = get_containers(&config, &address_cache, DEFAULT_TIMEZONE, deadline()).await;
 
         remote.assert_async().await;
         assert_that!(actual.is_err()).is_true();
-        assert_that!(actual.unwrap_err()).contains("Unexpected status code");
+        assert_that!(matches!(
+            actual.unwrap_err(),
+            Error::HttpStatus { status, .. } if status == reqwest::StatusCode::INTERNAL_SERVER_ERROR
+        ))
+        .is_true();
+    }
+
+    #[tokio::test]
+    async fn can_recover_from_transient_server_error() {
+        let mut remote = mockito::Server::new_async().await;
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
+        let config = AffaldVarmeConfig {
+            address: Address::Id(AddressId { id: "123".into() }),
+            base_url: Url::parse(remote.url().as_str()).unwrap(),
+            retry: fast_retry(),
+        };
+
+        let failing = remote
+            .mock("GET", "/api/calendar/address/123")
+            .with_status(502)
+            .expect(1)
+            .create_async()
+            .await;
+        let succeeding = remote
+            .mock("GET", "/api/calendar/address/123")
+            .with_status(200)
+            .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let actual = get_containers(&config, &address_cache, DEFAULT_TIMEZONE, deadline()).await;
+
+        failing.assert_async().await;
+        succeeding.assert_async().await;
+        assert_that!(actual.is_ok()).is_true();
+    }
+
+    #[tokio::test]
+    async fn can_respect_retry_after() {
+        let mut remote = mockito::Server::new_async().await;
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
+        let config = AffaldVarmeConfig {
+            address: Address::Id(AddressId { id: "123".into() }),
+            base_url: Url::parse(remote.url().as_str()).unwrap(),
+            retry: fast_retry(),
+        };
+
+        let throttled = remote
+            .mock("GET", "/api/calendar/address/123")
+            .with_status(429)
+            .with_header("Retry-After", "1")
+            .expect(1)
+            .create_async()
+            .await;
+        let succeeding = remote
+            .mock("GET", "/api/calendar/address/123")
+            .with_status(200)
+            .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let started = std::time::Instant::now();
+        let actual = get_containers(&config, &address_cache, DEFAULT_TIMEZONE, deadline()).await;
+
+        throttled.assert_async().await;
+        succeeding.assert_async().await;
+        assert_that!(actual.is_ok()).is_true();
+        assert_that!(started.elapsed() >= std::time::Duration::from_secs(1)).is_true();
+    }
+
+    #[tokio::test]
+    async fn can_give_up_when_retry_after_exceeds_deadline() {
+        let mut remote = mockito::Server::new_async().await;
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
+        let config = AffaldVarmeConfig {
+            address: Address::Id(AddressId { id: "123".into() }),
+            base_url: Url::parse(remote.url().as_str()).unwrap(),
+            retry: fast_retry(),
+        };
+
+        let remote = remote
+            .mock("GET", "/api/calendar/address/123")
+            .with_status(503)
+            .with_header("Retry-After", "3600")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let actual = get_containers(&config, &address_cache, DEFAULT_TIMEZONE, deadline()).await;
+
+        remote.assert_async().await;
+        assert_that!(matches!(
+            actual.unwrap_err(),
+            Error::HttpStatus { status, .. } if status == reqwest::StatusCode::SERVICE_UNAVAILABLE
+        ))
+        .is_true();
     }
 
     #[tokio::test]
     async fn can_handle_no_responses() {
+        let state_dir = tempfile::tempdir().unwrap();
+        let address_cache = AddressCache::new(state_dir.path());
         let config = AffaldVarmeConfig {
             address: Address::Id(AddressId { id: "123".into() }),
             base_url: Url::parse("http://127.0.0.1:12312").unwrap(),
+            retry: fast_retry(),
         };
 
-        let actual = get_containers(config).await;
+        let actual = get_containers(&config, &address_cache, DEFAULT_TIMEZONE, deadline()).await;
 
         assert_that!(actual.is_err()).is_true();
-        assert_that!(actual.unwrap_err()).contains("Error connecting");
+        assert_that!(matches!(actual.unwrap_err(), Error::Transport(_))).is_true();
+    }
+
+    fn fast_retry() -> RetryConfig {
+        RetryConfig {
+            max_attempts: 3,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 10,
+        }
+    }
+
+    fn deadline() -> Instant {
+        Instant::now() + std::time::Duration::from_secs(10)
     }
 }
@@ -1,31 +1,74 @@
 use serde::Deserialize;
 use url::Url;
 
-#[derive(Debug, Deserialize)]
+pub use crate::settings::RetryConfig;
+
+#[derive(Debug, Deserialize, Clone)]
 #[allow(unused)]
 pub struct AffaldVarmeConfig {
     pub address: Address,
     pub base_url: Url,
+    #[serde(default)]
+    pub retry: RetryConfig,
 }
 
-#[derive(Deserialize, Debug)]
+/// Kredsløb's calendar API.
+pub const DEFAULT_BASE_URL: &str = "https://portal-api.kredslob.dk";
+/// DAWA, the Danish address service.
+pub const DEFAULT_ADDRESS_LOOKUP_URL: &str = "https://api.dataforsyningen.dk";
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
 pub enum Address {
     Id(AddressId),
     FullySpecified(TraditionalAddress),
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct TraditionalAddress {
     pub street_name: String,
     pub street_no: String,
     pub postal_code: String,
     pub city: String,
+    /// Floor (`etage`), e.g. "st" or "2", needed for apartments.
+    #[serde(default)]
+    pub floor: Option<String>,
+    /// Door (`dør`), e.g. "tv", "th" or "12", needed for apartments.
+    #[serde(default)]
+    pub door: Option<String>,
 
     pub address_lookup_url: Url,
+
+    /// Look the address up again, even if its id was already resolved by an earlier sync.
+    #[serde(default)]
+    pub force_lookup: bool,
+}
+
+impl TraditionalAddress {
+    /// Identifies the address independently of casing and spacing.
+    pub fn cache_key(&self) -> String {
+        [
+            self.street_name.as_str(),
+            self.street_no.as_str(),
+            self.floor.as_deref().unwrap_or_default(),
+            self.door.as_deref().unwrap_or_default(),
+            self.postal_code.as_str(),
+            self.city.as_str(),
+        ]
+        .iter()
+        .map(|x| {
+            x.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct AddressId {
     pub id: String,
 }
@@ -0,0 +1,249 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    future::Future,
+};
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::{
+    cache::AddressCache,
+    error::Error,
+    fractions::Fraction,
+    ical::Ical,
+    mitaffald::Kredslob,
+    settings::{Language, ProviderConfig, StateConfig},
+};
+
+/// A planned pickup of one fraction, independent of where the schedule came from.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Container {
+    pub name: String,
+    /// The local date of the pickup.
+    pub date: NaiveDate,
+    pub stand_id: String,
+    pub stand_name: String,
+}
+
+impl Container {
+    pub fn new(name: String, date: NaiveDate, stand_id: String, stand_name: String) -> Self {
+        Self {
+            name,
+            date,
+            stand_id,
+            stand_name,
+        }
+    }
+}
+
+/// Every planned pickup of one fraction at one stand.
+#[derive(Debug, PartialEq)]
+pub struct FractionSchedule {
+    pub name: String,
+    /// The catalogue fraction `name` refers to.
+    pub fraction: Fraction,
+    pub stand_id: String,
+    pub stand_name: String,
+    /// Ordered, the earliest first.
+    pub dates: Vec<NaiveDate>,
+    /// Icon of the sensor, the default one when not configured.
+    pub icon: Option<String>,
+    /// `name` was configured in `[fractions]`, it is published as is rather than translated.
+    pub renamed: bool,
+}
+
+impl FractionSchedule {
+    /// Groups the containers per stand and fraction, in the order they were received.
+    ///
+    /// Names with the same slug, e.g. "Plast/metal" and "Plast metal", would be published as the
+    /// same sensor and are merged under the first of them, with a warning.
+    pub fn group(containers: Vec<Container>) -> Vec<FractionSchedule> {
+        let mut index = HashMap::<(String, String), usize>::new();
+        let mut schedules = Vec::<FractionSchedule>::new();
+        let mut merged = HashSet::<(String, String)>::new();
+
+        for container in containers {
+            let fraction = Fraction::from_name(&container.name);
+            match index.entry((container.stand_id.clone(), fraction.slug())) {
+                Entry::Occupied(existing) => {
+                    let schedule = &mut schedules[*existing.get()];
+                    if schedule.name != container.name
+                        && merged.insert((container.stand_id.clone(), container.name.clone()))
+                    {
+                        warn!(
+                            "Fraction '{}' of stand {} is merged into '{}', both map to the same sensor",
+                            container.name, container.stand_id, schedule.name
+                        );
+                    }
+                    schedule.dates.push(container.date);
+                }
+                Entry::Vacant(v) => {
+                    v.insert(schedules.len());
+                    schedules.push(FractionSchedule {
+                        fraction,
+                        name: container.name,
+                        stand_id: container.stand_id,
+                        stand_name: container.stand_name,
+                        dates: vec![container.date],
+                        icon: None,
+                        renamed: false,
+                    });
+                }
+            }
+        }
+
+        for schedule in &mut schedules {
+            schedule.dates.sort();
+            schedule.dates.dedup();
+        }
+
+        schedules
+    }
+
+    /// The name of the sensor, in `language` unless it was configured.
+    pub fn display_name(&self, language: Language) -> &str {
+        if self.renamed {
+            &self.name
+        } else {
+            self.fraction.name(language)
+        }
+    }
+
+    /// The earliest planned pickup.
+    pub fn next(&self) -> NaiveDate {
+        self.dates[0]
+    }
+}
+
+/// A source of collection schedules, e.g. a municipality or waste company API.
+pub trait WasteProvider {
+    /// Every planned pickup known by the provider.
+    fn get_containers(&self) -> impl Future<Output = Result<Vec<Container>, Error>> + Send;
+}
+
+/// The provider selected in the settings of an address.
+#[allow(clippy::large_enum_variant)]
+pub enum Provider {
+    Kredslob(Kredslob),
+    Ical(Ical),
+}
+
+impl Provider {
+    /// A provider that gives up on its requests at `deadline`.
+    pub fn new(
+        config: &ProviderConfig,
+        state: &StateConfig,
+        timezone: Tz,
+        deadline: Instant,
+    ) -> Self {
+        match config {
+            ProviderConfig::Kredslob(config) => Provider::Kredslob(Kredslob::new(
+                config.clone(),
+                AddressCache::new(&state.dir),
+                timezone,
+                deadline,
+            )),
+            ProviderConfig::Ical(config) => {
+                Provider::Ical(Ical::new(config.clone(), timezone, deadline))
+            }
+        }
+    }
+}
+
+impl WasteProvider for Provider {
+    async fn get_containers(&self) -> Result<Vec<Container>, Error> {
+        match self {
+            Provider::Kredslob(provider) => provider.get_containers().await,
+            Provider::Ical(provider) => provider.get_containers().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(name: &str, date: &str, stand_id: &str) -> Container {
+        Container::new(
+            name.to_string(),
+            date.parse().unwrap(),
+            stand_id.to_string(),
+            format!("Stand {}", stand_id),
+        )
+    }
+
+    #[test]
+    fn groups_dates_per_stand_and_fraction() {
+        let actual = FractionSchedule::group(vec![
+            container("Restaffald", "2024-05-02", "1"),
+            container("Glas", "2024-04-26", "1"),
+            container("Restaffald", "2024-04-18", "1"),
+            container("Restaffald", "2024-04-19", "2"),
+            container("Restaffald", "2024-04-18", "1"),
+        ]);
+
+        let actual: Vec<_> = actual
+            .iter()
+            .map(|x| {
+                (
+                    x.stand_id.as_str(),
+                    x.name.as_str(),
+                    x.dates.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            actual,
+            vec![
+                (
+                    "1",
+                    "Restaffald",
+                    vec!["2024-04-18".to_string(), "2024-05-02".to_string()]
+                ),
+                ("1", "Glas", vec!["2024-04-26".to_string()]),
+                ("2", "Restaffald", vec!["2024-04-19".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_names_with_the_same_slug() {
+        let actual = FractionSchedule::group(vec![
+            container("Plast/metal", "2024-05-02", "1"),
+            container("Plast metal", "2024-04-18", "1"),
+            container("Garden waste", "2024-04-19", "1"),
+            container("Haveaffald", "2024-05-03", "1"),
+            container("Plast metal", "2024-04-25", "2"),
+        ]);
+
+        let actual: Vec<_> = actual
+            .iter()
+            .map(|x| {
+                (
+                    x.stand_id.as_str(),
+                    x.name.as_str(),
+                    x.dates.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            actual,
+            vec![
+                (
+                    "1",
+                    "Plast/metal",
+                    vec!["2024-04-18".to_string(), "2024-05-02".to_string()]
+                ),
+                (
+                    "1",
+                    "Garden waste",
+                    vec!["2024-04-19".to_string(), "2024-05-03".to_string()]
+                ),
+                ("2", "Plast metal", vec!["2024-04-25".to_string()]),
+            ]
+        );
+    }
+}
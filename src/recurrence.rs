@@ -0,0 +1,181 @@
+use chrono::{Datelike, Days, NaiveDate, Weekday};
+
+/// The regular rhythm of a fraction, e.g. every 14 days on Thursday.
+///
+/// Pickups are planned in whole weeks, dates off that rhythm are pickups moved around a
+/// holiday.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recurrence {
+    pub interval_days: i64,
+    /// A regular pickup, every other one is a whole number of intervals away.
+    anchor: NaiveDate,
+}
+
+impl Recurrence {
+    /// The rhythm most of `dates` follow, unknown when there are too few dates or no majority.
+    pub fn infer(dates: &[NaiveDate]) -> Option<Self> {
+        if dates.len() < 3 {
+            return None;
+        }
+
+        let weeks = mode(
+            dates
+                .windows(2)
+                .map(|x| ((x[1] - x[0]).num_days() + 3) / 7)
+                .filter(|x| *x > 0),
+        )?;
+        let interval_days = weeks * 7;
+
+        let phase = |date: &NaiveDate| i64::from(date.num_days_from_ce()).rem_euclid(interval_days);
+        let regular = mode(dates.iter().map(phase))?;
+        let anchor = *dates.iter().find(|x| phase(x) == regular)?;
+
+        let recurrence = Recurrence {
+            interval_days,
+            anchor,
+        };
+        let regular = dates.iter().filter(|x| recurrence.is_regular(**x)).count();
+        (regular * 2 > dates.len()).then_some(recurrence)
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        self.anchor.weekday()
+    }
+
+    pub fn is_regular(&self, date: NaiveDate) -> bool {
+        (date - self.anchor)
+            .num_days()
+            .rem_euclid(self.interval_days)
+            == 0
+    }
+
+    /// The regular dates after `date`, a moved pickup on `date` counts for its original date.
+    pub fn after(&self, date: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+        let date = self.original_date(date).map_or(date, |x| x.max(date));
+        let offset = (date - self.anchor)
+            .num_days()
+            .rem_euclid(self.interval_days);
+        let interval = Days::new(self.interval_days as u64);
+        let first = date + Days::new((self.interval_days - offset) as u64);
+
+        std::iter::successors(Some(first), move |x| x.checked_add_days(interval))
+    }
+
+    /// The regular date closest to `date`, none when `date` is regular.
+    pub fn original_date(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let offset = (date - self.anchor)
+            .num_days()
+            .rem_euclid(self.interval_days);
+        match offset {
+            0 => None,
+            x if x <= self.interval_days / 2 => date.checked_sub_days(Days::new(x as u64)),
+            x => date.checked_add_days(Days::new((self.interval_days - x) as u64)),
+        }
+    }
+}
+
+/// The most common value, ties go to the one seen first.
+fn mode(values: impl Iterator<Item = i64>) -> Option<i64> {
+    let values: Vec<i64> = values.collect();
+
+    values
+        .iter()
+        .rev()
+        .max_by_key(|x| values.iter().filter(|y| y == x).count())
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dates(values: &[&str]) -> Vec<NaiveDate> {
+        values.iter().map(|x| x.parse().unwrap()).collect()
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn infers_interval_and_weekday() {
+        let actual = Recurrence::infer(&dates(&[
+            "2024-04-18",
+            "2024-05-02",
+            "2024-05-16",
+            "2024-05-30",
+        ]))
+        .unwrap();
+
+        assert_eq!(actual.interval_days, 14);
+        assert_eq!(actual.weekday(), Weekday::Thu);
+    }
+
+    #[test]
+    fn finds_pickups_moved_by_holidays() {
+        // Friday pickups, Good Friday 2024-03-29 moved to Saturday
+        let dates = dates(&[
+            "2024-03-15",
+            "2024-03-22",
+            "2024-03-30",
+            "2024-04-05",
+            "2024-04-12",
+        ]);
+
+        let actual = Recurrence::infer(&dates).unwrap();
+
+        assert_eq!(actual.interval_days, 7);
+        assert_eq!(actual.weekday(), Weekday::Fri);
+        let moved: Vec<_> = dates
+            .iter()
+            .filter_map(|x| actual.original_date(*x).map(|original| (*x, original)))
+            .collect();
+        assert_eq!(moved, vec![(date("2024-03-30"), date("2024-03-29"))]);
+    }
+
+    #[test]
+    fn moved_pickup_can_be_the_first() {
+        let dates = dates(&["2024-05-04", "2024-05-16", "2024-05-30", "2024-06-13"]);
+
+        let actual = Recurrence::infer(&dates).unwrap();
+
+        assert_eq!(actual.weekday(), Weekday::Thu);
+        assert_eq!(
+            actual.original_date(date("2024-05-04")),
+            Some(date("2024-05-02"))
+        );
+    }
+
+    #[test]
+    fn continues_the_rhythm() {
+        let recurrence =
+            Recurrence::infer(&dates(&["2024-04-18", "2024-05-02", "2024-05-16"])).unwrap();
+
+        let actual: Vec<_> = recurrence.after(date("2024-05-16")).take(2).collect();
+        assert_eq!(actual, dates(&["2024-05-30", "2024-06-13"]));
+
+        let actual: Vec<_> = recurrence.after(date("2024-05-18")).take(1).collect();
+        assert_eq!(actual, dates(&["2024-05-30"]));
+
+        // moved a day ahead of 2024-05-16
+        let actual: Vec<_> = recurrence.after(date("2024-05-15")).take(1).collect();
+        assert_eq!(actual, dates(&["2024-05-30"]));
+    }
+
+    #[test]
+    fn unknown_without_enough_dates_or_majority() {
+        assert_eq!(
+            Recurrence::infer(&dates(&["2024-04-18", "2024-05-02"])),
+            None
+        );
+        assert_eq!(
+            Recurrence::infer(&dates(&[
+                "2024-04-15",
+                "2024-04-23",
+                "2024-05-01",
+                "2024-05-09"
+            ])),
+            None
+        );
+    }
+}
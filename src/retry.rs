@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use tokio::time::Instant;
+use tracing::warn;
+use url::Url;
+
+use crate::{error::Error, settings::RetryConfig};
+
+/// Issues GET requests, retrying transient failures with exponential backoff and jitter.
+/// No request outlives `deadline`, which is shared by everything a synchronization fetches.
+pub(crate) struct RetryingClient {
+    config: RetryConfig,
+    deadline: Instant,
+}
+
+impl RetryingClient {
+    pub fn new(config: &RetryConfig, deadline: Instant) -> Self {
+        Self {
+            config: config.clone(),
+            deadline,
+        }
+    }
+
+    pub async fn get(&self, url: Url) -> Result<Response, Error> {
+        let mut attempt = 1;
+
+        loop {
+            let remaining = self.deadline.saturating_duration_since(Instant::now());
+            let outcome = tokio::time::timeout(remaining, reqwest::get(url.clone()))
+                .await
+                .map_err(|_| Error::DeadlineExceeded)?;
+
+            let delay = match &outcome {
+                Ok(response) if is_transient_status(response.status()) => {
+                    retry_after(response).unwrap_or_else(|| self.backoff(attempt))
+                }
+                Err(err) if err.is_connect() || err.is_timeout() => self.backoff(attempt),
+                _ => return outcome.map_err(Error::Transport),
+            };
+
+            if attempt >= self.config.max_attempts || Instant::now() + delay >= self.deadline {
+                return outcome.map_err(Error::Transport);
+            }
+
+            match &outcome {
+                Ok(response) => warn!(
+                    "Attempt {} for {} returned {}, retrying in {:?}",
+                    attempt,
+                    url,
+                    response.status(),
+                    delay
+                ),
+                Err(err) => warn!(
+                    "Attempt {} for {} failed: {}, retrying in {:?}",
+                    attempt, url, err, delay
+                ),
+            }
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Exponential backoff with "equal jitter": somewhere between half and the full delay.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .config
+            .initial_backoff_ms
+            .saturating_mul(2u64.saturating_pow(attempt - 1))
+            .min(self.config.max_backoff_ms);
+
+        Duration::from_millis(delay / 2 + rand::rng().random_range(0..=delay - delay / 2))
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Honors `Retry-After` (delay in seconds or an HTTP date) on 429 and 503 responses.
+fn retry_after(response: &Response) -> Option<Duration> {
+    if !matches!(
+        response.status(),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return None;
+    }
+
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    DateTime::parse_from_rfc2822(value).ok().map(|date| {
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default()
+    })
+}
@@ -1,62 +1,132 @@
 mod mqtt;
 
-use crate::mqtt::CollectingClient;
+use crate::mqtt::{retained_v5, CollectingClient};
+use chrono::NaiveDate;
 use ha_mitaffald::{
-    mitaffald::settings::{Address, AddressId, AffaldVarmeConfig},
-    settings::Settings,
-    sync_data,
+    cache::{AddressCache, PlanCache},
+    clock::DEFAULT_TIMEZONE,
+    error::Error,
+    homeassistant::MqttClient,
+    mitaffald::{
+        get_containers,
+        settings::{Address, AddressId, AffaldVarmeConfig, RetryConfig},
+    },
+    settings::{
+        AddressConfig, Language, MQTTConfig, MqttTransport, MqttVersion, ProviderConfig, Settings,
+        StateConfig, TlsConfig,
+    },
+    synchronize, Source,
 };
 use rumqttc::Publish;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use testcontainers::{core::WaitFor, runners::AsyncRunner, GenericImage};
+use std::{collections::HashSet, path::Path, time::Duration};
+use testcontainers::{
+    core::{IntoContainerPort, WaitFor},
+    runners::AsyncRunner,
+    ContainerAsync, GenericImage, ImageExt,
+};
+use tokio::time::Instant;
 use url::Url;
 
 #[tokio::test]
 async fn smoke_test_insta() {
-    let mqtt_server = GenericImage::new("hivemq/hivemq-ce", "latest")
-        .with_wait_for(WaitFor::message_on_stdout("Started HiveMQ in"))
-        .start()
-        .await
-        .expect("Failed to start container, is Docker running?");
-
-    let mqtt_server_port = mqtt_server
-        .get_host_port_ipv4(1883)
-        .await
-        .expect("Failed to get port binding");
+    let (_mqtt_server, mqtt_server_port) = start_hivemq().await;
 
     let mut mit_affald_server = mockito::Server::new_async().await;
-    let mit_affald_server_url = Url::parse(&mit_affald_server.url()).unwrap();
-    let address_id = "123".to_string();
+    let mit_affald_server_url = mit_affald_server.url();
     let mit_affald_server = mit_affald_server
-        .mock(
-            "GET",
-            format!("/api/calendar/address/{}", address_id).as_str(),
-        )
+        .mock("GET", "/api/calendar/address/123")
         .with_status(200)
         .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
         .create_async()
         .await;
 
-    let settings = Settings {
-        update_interval_minutes: 60,
-        affaldvarme: AffaldVarmeConfig {
-            address: Address::Id(AddressId { id: address_id }),
-            base_url: mit_affald_server_url,
-        },
-        mqtt: ha_mitaffald::settings::MQTTConfig {
-            client_id: "test".to_string(),
-            host: "localhost".to_string(),
-            port: mqtt_server_port,
-            username: "".to_owned(),
-            password: "".to_owned(),
+    let state_dir = tempfile::tempdir().unwrap();
+    let settings = settings(
+        mqtt_server_port,
+        kredslob(&mit_affald_server_url),
+        state_dir.path(),
+    );
+
+    let mut home_assistant = CollectingClient::new();
+    home_assistant.start(&settings.mqtt);
+
+    let sync_result = synchronize(settings, Source::Provider, today()).await;
+
+    assert!(
+        sync_result.is_ok(),
+        "Error synchronizing: {:?}",
+        sync_result.err()
+    );
+
+    let ha_messages_result = home_assistant.wait_for_messages(22, Duration::from_secs(60));
+
+    assert!(
+        ha_messages_result.is_ok(),
+        "Error waiting for messages: {}",
+        ha_messages_result.unwrap_err()
+    );
+
+    mit_affald_server.assert_async().await;
+
+    let actual = actual(ha_messages_result.unwrap());
+
+    insta::with_settings!({
+        filters=>vec![
+            (r#"\\"last_update\\":\s*\\"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}\.\d+\+\d{2}:\d{2}\\""#,
+            r#"\"last_update\": \"[REDACTED]\""#)
+        ]
+    }, {
+        insta::assert_yaml_snapshot!(actual);
+    });
+}
+
+#[tokio::test]
+async fn serves_cached_plan_when_remote_fails() {
+    let (_mqtt_server, mqtt_server_port) = start_hivemq().await;
+
+    let mut mit_affald_server = mockito::Server::new_async().await;
+    let mit_affald_server_url = mit_affald_server.url();
+    let affaldvarme = || AffaldVarmeConfig {
+        retry: RetryConfig {
+            max_attempts: 1,
+            ..Default::default()
         },
+        ..kredslob(&mit_affald_server_url)
     };
 
+    let state_dir = tempfile::tempdir().unwrap();
+    let working_server = mit_affald_server
+        .mock("GET", "/api/calendar/address/123")
+        .with_status(200)
+        .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+        .create_async()
+        .await;
+    let containers = get_containers(
+        &affaldvarme(),
+        &AddressCache::new(state_dir.path()),
+        DEFAULT_TIMEZONE,
+        deadline(),
+    )
+    .await
+    .unwrap();
+    PlanCache::new(state_dir.path(), "affaldvarme")
+        .store(&containers)
+        .unwrap();
+    working_server.remove_async().await;
+
+    let failing_server = mit_affald_server
+        .mock("GET", "/api/calendar/address/123")
+        .with_status(500)
+        .create_async()
+        .await;
+
+    let settings = settings(mqtt_server_port, affaldvarme(), state_dir.path());
+
     let mut home_assistant = CollectingClient::new();
     home_assistant.start(&settings.mqtt);
 
-    let sync_result = sync_data(settings).await;
+    let sync_result = synchronize(settings, Source::Provider, today()).await;
 
     assert!(
         sync_result.is_ok(),
@@ -64,7 +134,7 @@ async fn smoke_test_insta() {
         sync_result.err()
     );
 
-    let ha_messages_result = home_assistant.wait_for_messages(20, Duration::from_secs(60));
+    let ha_messages_result = home_assistant.wait_for_messages(22, Duration::from_secs(60));
 
     assert!(
         ha_messages_result.is_ok(),
@@ -72,7 +142,73 @@ async fn smoke_test_insta() {
         ha_messages_result.unwrap_err()
     );
 
-    mit_affald_server.assert_async().await;
+    failing_server.assert_async().await;
+
+    let actual = actual(ha_messages_result.unwrap());
+
+    insta::with_settings!({
+        filters=>vec![
+            (r#"\\"last_update\\":\s*\\"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}\.\d+\+\d{2}:\d{2}\\""#,
+            r#"\"last_update\": \"[REDACTED]\""#),
+            (r#"\\"fetched_at\\":\s*\\"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}\.\d+\+\d{2}:\d{2}\\""#,
+            r#"\"fetched_at\": \"[REDACTED]\""#)
+        ]
+    }, {
+        insta::assert_yaml_snapshot!(actual);
+    });
+}
+
+#[tokio::test]
+async fn rolls_over_using_cached_plan() {
+    let (_mqtt_server, mqtt_server_port) = start_hivemq().await;
+
+    let mut mit_affald_server = mockito::Server::new_async().await;
+    let affaldvarme = kredslob(&mit_affald_server.url());
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let working_server = mit_affald_server
+        .mock("GET", "/api/calendar/address/123")
+        .with_status(200)
+        .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+        .expect(1)
+        .create_async()
+        .await;
+    let containers = get_containers(
+        &affaldvarme,
+        &AddressCache::new(state_dir.path()),
+        DEFAULT_TIMEZONE,
+        deadline(),
+    )
+    .await
+    .unwrap();
+    PlanCache::new(state_dir.path(), "affaldvarme")
+        .store(&containers)
+        .unwrap();
+
+    let settings = settings(mqtt_server_port, affaldvarme, state_dir.path());
+
+    let mut home_assistant = CollectingClient::new();
+    home_assistant.start(&settings.mqtt);
+
+    // the day after the first pickups of the plan
+    let sync_result = synchronize(settings, Source::Cache, "2024-04-19".parse().unwrap()).await;
+
+    assert!(
+        sync_result.is_ok(),
+        "Error synchronizing: {:?}",
+        sync_result.err()
+    );
+
+    let ha_messages_result = home_assistant.wait_for_messages(22, Duration::from_secs(60));
+
+    assert!(
+        ha_messages_result.is_ok(),
+        "Error waiting for messages: {}",
+        ha_messages_result.unwrap_err()
+    );
+
+    // only the plan fetched above, rolling over never contacts the provider
+    working_server.assert_async().await;
 
     let actual = actual(ha_messages_result.unwrap());
 
@@ -86,6 +222,425 @@ async fn smoke_test_insta() {
     });
 }
 
+#[tokio::test]
+async fn reports_a_plan_that_could_not_be_cached() {
+    let (_mqtt_server, mqtt_server_port) = start_hivemq().await;
+
+    let mut mit_affald_server = mockito::Server::new_async().await;
+    let mit_affald_server_url = mit_affald_server.url();
+    let mit_affald_server = mit_affald_server
+        .mock("GET", "/api/calendar/address/123")
+        .with_status(200)
+        .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+        .create_async()
+        .await;
+
+    // a file where the state directory should be
+    let state_file = tempfile::NamedTempFile::new().unwrap();
+    let settings = settings(
+        mqtt_server_port,
+        kredslob(&mit_affald_server_url),
+        state_file.path(),
+    );
+
+    let mut home_assistant = CollectingClient::new();
+    home_assistant.start(&settings.mqtt);
+
+    let sync_result = synchronize(settings, Source::Provider, today()).await;
+
+    assert!(
+        matches!(sync_result, Err(Error::WriteFile { .. })),
+        "Expected the cache to fail: {:?}",
+        sync_result
+    );
+
+    // published all the same
+    let ha_messages_result = home_assistant.wait_for_messages(22, Duration::from_secs(60));
+
+    assert!(
+        ha_messages_result.is_ok(),
+        "Error waiting for messages: {}",
+        ha_messages_result.unwrap_err()
+    );
+
+    mit_affald_server.assert_async().await;
+}
+
+#[tokio::test]
+async fn publishes_schedule_changes() {
+    let (_mqtt_server, mqtt_server_port) = start_hivemq().await;
+
+    let mut mit_affald_server = mockito::Server::new_async().await;
+    let affaldvarme = kredslob(&mit_affald_server.url());
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let working_server = mit_affald_server
+        .mock("GET", "/api/calendar/address/123")
+        .with_status(200)
+        .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+        .expect(2)
+        .create_async()
+        .await;
+
+    // the previous plan had Restaffald a day later and one Glas pickup less
+    let mut previous = get_containers(
+        &affaldvarme,
+        &AddressCache::new(state_dir.path()),
+        DEFAULT_TIMEZONE,
+        deadline(),
+    )
+    .await
+    .unwrap();
+    let moved = previous
+        .iter()
+        .position(|x| x.name == "Restaffald" && x.date > today())
+        .unwrap();
+    previous[moved].date = previous[moved].date.succ_opt().unwrap();
+    let added = previous
+        .iter()
+        .position(|x| x.name == "Glas" && x.date > today())
+        .unwrap();
+    previous.remove(added);
+    PlanCache::new(state_dir.path(), "affaldvarme")
+        .store(&previous)
+        .unwrap();
+
+    let settings = settings(mqtt_server_port, affaldvarme, state_dir.path());
+
+    let mut home_assistant = CollectingClient::new();
+    home_assistant.start(&settings.mqtt);
+
+    let sync_result = synchronize(settings, Source::Provider, today()).await;
+
+    assert!(
+        sync_result.is_ok(),
+        "Error synchronizing: {:?}",
+        sync_result.err()
+    );
+
+    let ha_messages_result = home_assistant.wait_for_messages(24, Duration::from_secs(60));
+
+    assert!(
+        ha_messages_result.is_ok(),
+        "Error waiting for messages: {}",
+        ha_messages_result.unwrap_err()
+    );
+
+    working_server.assert_async().await;
+
+    let actual: Vec<MqttMessage> = actual(ha_messages_result.unwrap())
+        .into_iter()
+        .filter(|x| x.topic.ends_with("/changes"))
+        .collect();
+
+    insta::assert_yaml_snapshot!(actual);
+}
+
+#[tokio::test]
+async fn synchronizes_over_mutual_tls() {
+    let certs = std::path::Path::new("tests/certs");
+    let tls_server = GenericImage::new("eclipse-mosquitto", "2")
+        .with_exposed_port(8883.tcp())
+        .with_wait_for(WaitFor::message_on_stdout("running"))
+        .with_copy_to(
+            "/mosquitto/config/mosquitto.conf",
+            certs.join("mosquitto.conf"),
+        )
+        .with_copy_to("/mosquitto/certs/ca.crt", certs.join("ca.crt"))
+        .with_copy_to("/mosquitto/certs/server.crt", certs.join("server.crt"))
+        .with_copy_to("/mosquitto/certs/server.key", certs.join("server.key"))
+        .start()
+        .await
+        .expect("Failed to start container, is Docker running?");
+
+    let mqtt_server_port = tls_server
+        .get_host_port_ipv4(8883)
+        .await
+        .expect("Failed to get TLS port binding");
+
+    let mut mit_affald_server = mockito::Server::new_async().await;
+    let mit_affald_server_url = mit_affald_server.url();
+    let mit_affald_server = mit_affald_server
+        .mock("GET", "/api/calendar/address/123")
+        .with_status(200)
+        .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+        .create_async()
+        .await;
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let mut settings = settings(
+        mqtt_server_port,
+        kredslob(&mit_affald_server_url),
+        state_dir.path(),
+    );
+    settings.mqtt.tls = Some(TlsConfig {
+        ca_file: Some(certs.join("ca.crt")),
+        client_cert: Some(certs.join("client.crt")),
+        client_key: Some(certs.join("client.key")),
+        insecure_skip_verify: false,
+    });
+
+    let mut home_assistant = CollectingClient::new();
+    home_assistant.start(&settings.mqtt);
+
+    let sync_result = synchronize(settings, Source::Provider, today()).await;
+
+    assert!(
+        sync_result.is_ok(),
+        "Error synchronizing: {:?}",
+        sync_result.err()
+    );
+
+    let ha_messages_result = home_assistant.wait_for_messages(22, Duration::from_secs(60));
+
+    assert!(
+        ha_messages_result.is_ok(),
+        "Error waiting for messages: {}",
+        ha_messages_result.unwrap_err()
+    );
+
+    mit_affald_server.assert_async().await;
+
+    let actual = actual(ha_messages_result.unwrap());
+    assert!(actual
+        .iter()
+        .any(|x| x.topic == "garbage_bin/affaldvarme/restaffald/status"));
+}
+
+#[tokio::test]
+async fn synchronizes_over_websockets() {
+    let ws_server = GenericImage::new("eclipse-mosquitto", "2")
+        .with_exposed_port(8080.tcp())
+        .with_wait_for(WaitFor::message_on_stdout("running"))
+        .with_copy_to(
+            "/mosquitto/config/mosquitto.conf",
+            b"listener 8080
+protocol websockets
+allow_anonymous true
+log_dest stdout
+"
+            .to_vec(),
+        )
+        .start()
+        .await
+        .expect("Failed to start container, is Docker running?");
+
+    let mqtt_server_port = ws_server
+        .get_host_port_ipv4(8080)
+        .await
+        .expect("Failed to get WebSocket port binding");
+
+    let mut mit_affald_server = mockito::Server::new_async().await;
+    let mit_affald_server_url = mit_affald_server.url();
+    let mit_affald_server = mit_affald_server
+        .mock("GET", "/api/calendar/address/123")
+        .with_status(200)
+        .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+        .create_async()
+        .await;
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let mut settings = settings(
+        mqtt_server_port,
+        kredslob(&mit_affald_server_url),
+        state_dir.path(),
+    );
+    settings.mqtt.transport = Some(MqttTransport::Ws);
+
+    let mut home_assistant = CollectingClient::new();
+    home_assistant.start(&settings.mqtt);
+
+    let sync_result = synchronize(settings, Source::Provider, today()).await;
+
+    assert!(
+        sync_result.is_ok(),
+        "Error synchronizing: {:?}",
+        sync_result.err()
+    );
+
+    let ha_messages_result = home_assistant.wait_for_messages(22, Duration::from_secs(60));
+
+    assert!(
+        ha_messages_result.is_ok(),
+        "Error waiting for messages: {}",
+        ha_messages_result.unwrap_err()
+    );
+
+    mit_affald_server.assert_async().await;
+
+    let actual = actual(ha_messages_result.unwrap());
+    assert!(actual
+        .iter()
+        .any(|x| x.topic == "garbage_bin/affaldvarme/restaffald/status"));
+}
+
+#[tokio::test]
+async fn publishes_mqtt5_properties() {
+    let (_mqtt_server, mqtt_server_port) = start_hivemq().await;
+
+    let mut mit_affald_server = mockito::Server::new_async().await;
+    let mit_affald_server_url = mit_affald_server.url();
+    let mit_affald_server = mit_affald_server
+        .mock("GET", "/api/calendar/address/123")
+        .with_status(200)
+        .with_body_from_file("src/mitaffald/remote_responses/container_information.json")
+        .create_async()
+        .await;
+
+    let state_dir = tempfile::tempdir().unwrap();
+    let mut settings = settings(
+        mqtt_server_port,
+        kredslob(&mit_affald_server_url),
+        state_dir.path(),
+    );
+    settings.mqtt.version = MqttVersion::V5;
+    let mqtt = settings.mqtt.clone();
+
+    let sync_result = synchronize(settings, Source::Provider, today()).await;
+
+    assert!(
+        sync_result.is_ok(),
+        "Error synchronizing: {:?}",
+        sync_result.err()
+    );
+
+    mit_affald_server.assert_async().await;
+
+    let states = retained_v5(&mqtt, "garbage_bin/affaldvarme/+/status").await;
+    assert!(!states.is_empty(), "No states retained");
+
+    let run_ids: HashSet<String> = states
+        .iter()
+        .map(|state| {
+            let properties = state.properties.clone().expect("No properties");
+            // two update intervals, minus the time it took to get here
+            assert!(properties
+                .message_expiry_interval
+                .is_some_and(|x| x > 7000 && x <= 7200));
+
+            properties
+                .user_properties
+                .into_iter()
+                .find(|(key, _)| key == "run_id")
+                .map(|(_, value)| value)
+                .expect("No run id")
+        })
+        .collect();
+    assert_eq!(run_ids.len(), 1);
+}
+
+#[tokio::test]
+async fn publishes_more_messages_than_buffered() {
+    let (_mqtt_server, mqtt_server_port) = start_hivemq().await;
+    let mqtt = mqtt_config(mqtt_server_port);
+
+    let mut home_assistant = CollectingClient::new();
+    home_assistant.start(&mqtt);
+
+    let (client, connection) = MqttClient::new(mqtt.try_into().unwrap(), 5);
+    let connection = tokio::spawn(connection.run());
+
+    let published = tokio::time::timeout(Duration::from_secs(60), async move {
+        for i in 0..50 {
+            client
+                .publish(format!("garbage_bin/test/{}", i), false, "payload")
+                .await?;
+        }
+        client.close().await
+    })
+    .await;
+
+    assert!(
+        matches!(published, Ok(Ok(()))),
+        "Error publishing: {:?}",
+        published
+    );
+
+    let rejected = connection.await.unwrap();
+    assert!(
+        rejected.as_ref().is_ok_and(|x| x.is_empty()),
+        "Error sending: {:?}",
+        rejected
+    );
+
+    let ha_messages_result = home_assistant.wait_for_messages(50, Duration::from_secs(60));
+
+    assert!(
+        ha_messages_result.is_ok(),
+        "Error waiting for messages: {}",
+        ha_messages_result.unwrap_err()
+    );
+}
+
+/// Starts a HiveMQ broker, kept running as long as the returned container lives.
+async fn start_hivemq() -> (ContainerAsync<GenericImage>, u16) {
+    let mqtt_server = GenericImage::new("hivemq/hivemq-ce", "latest")
+        .with_wait_for(WaitFor::message_on_stdout("Started HiveMQ in"))
+        .start()
+        .await
+        .expect("Failed to start container, is Docker running?");
+
+    let mqtt_server_port = mqtt_server
+        .get_host_port_ipv4(1883)
+        .await
+        .expect("Failed to get port binding");
+
+    (mqtt_server, mqtt_server_port)
+}
+
+/// Settings for a single address, published to the broker listening on `broker_port` on localhost.
+fn settings(broker_port: u16, affaldvarme: AffaldVarmeConfig, state_dir: &Path) -> Settings {
+    Settings {
+        update_interval_minutes: 60,
+        affaldvarme: vec![AddressConfig {
+            id: "affaldvarme".into(),
+            name: "Affaldvarme Device".into(),
+            upcoming_days: 60,
+            predict: false,
+            predict_days: 180,
+            provider: ProviderConfig::Kredslob(affaldvarme),
+        }],
+        state: StateConfig {
+            dir: state_dir.to_owned(),
+            ..Default::default()
+        },
+        timezone: DEFAULT_TIMEZONE,
+        fractions: Default::default(),
+        language: Language::Da,
+        sync_deadline_seconds: 120,
+        mqtt: mqtt_config(broker_port),
+    }
+}
+
+/// MQTT 3.1.1 over TCP to the broker listening on `broker_port` on localhost.
+fn mqtt_config(broker_port: u16) -> MQTTConfig {
+    MQTTConfig {
+        client_id: "test".to_string(),
+        host: "localhost".to_string(),
+        port: broker_port,
+        ..Default::default()
+    }
+}
+
+/// Address 123 at the Kredsløb API served from `base_url`.
+fn kredslob(base_url: &str) -> AffaldVarmeConfig {
+    AffaldVarmeConfig {
+        address: Address::Id(AddressId { id: "123".into() }),
+        base_url: Url::parse(base_url).unwrap(),
+        retry: Default::default(),
+    }
+}
+
+/// Plenty of time for the mocked provider.
+fn deadline() -> Instant {
+    Instant::now() + Duration::from_secs(60)
+}
+
+/// A fixed day, so the pickups in the remote responses stay in the future.
+fn today() -> NaiveDate {
+    "2024-04-20".parse().unwrap()
+}
+
 fn actual(messages: Vec<Publish>) -> Vec<MqttMessage> {
     let mut x: Vec<MqttMessage> = messages
         .iter()
@@ -5,7 +5,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use rumqttc::{Client, Event, Packet, Publish, QoS};
+use rumqttc::{v5, Client, Event, Packet, Publish, QoS};
 use tracing::info;
 
 pub struct CollectingClient {
@@ -33,7 +33,7 @@ impl CollectingClient {
         let (tx, rx) = std::sync::mpsc::channel::<()>();
 
         let handle = std::thread::spawn(move || {
-            let (client, mut connection) = Client::new(config.into(), 100);
+            let (client, mut connection) = Client::new(config.try_into().unwrap(), 100);
             client.subscribe("#", QoS::AtLeastOnce).unwrap();
 
             loop {
@@ -101,6 +101,33 @@ impl CollectingClient {
     }
 }
 
+/// The retained messages matching `filter`, as a new MQTT 5 subscriber receives them.
+pub async fn retained_v5(
+    config: &ha_mitaffald::settings::MQTTConfig,
+    filter: &str,
+) -> Vec<v5::mqttbytes::v5::Publish> {
+    let config = ha_mitaffald::settings::MQTTConfig {
+        client_id: "retained-client".to_owned(),
+        ..config.clone()
+    };
+    let (client, mut connection) = v5::AsyncClient::new(config.try_into().unwrap(), 100);
+    client
+        .subscribe(filter, v5::mqttbytes::QoS::AtLeastOnce)
+        .await
+        .unwrap();
+
+    // retained messages arrive right after subscribing, nothing else is published meanwhile
+    let mut messages = Vec::new();
+    while let Ok(Ok(event)) = tokio::time::timeout(Duration::from_secs(2), connection.poll()).await
+    {
+        if let v5::Event::Incoming(v5::Incoming::Publish(message)) = event {
+            messages.push(message);
+        }
+    }
+
+    messages
+}
+
 #[derive(Debug)]
 pub enum WaitError {
     Timeout(Vec<Publish>),